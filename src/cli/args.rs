@@ -1,11 +1,37 @@
-use crate::core::task::Priority;
-use clap::{Parser, Subcommand, ValueEnum};
+use crate::core::filter::StatusMatch;
+use crate::core::group::GroupBy;
+use crate::core::recurrence::Recurrence;
+use crate::core::sort::SortOrder;
+use crate::core::task::{Estimate, Priority, Status};
+use crate::core::time::Zone;
+use crate::core::view::{Columns, View};
+use crate::ui::output::Format;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "todo")]
 #[command(about="A modern todo list manager", long_about=None)]
 #[command(version)]
 pub struct Cli {
+    /// Storage backend to keep tasks in
+    #[arg(long, global = true, value_enum, env = "TODO_STORAGE", default_value_t = StorageArg::Json)]
+    pub storage: StorageArg,
+
+    /// Time zone to read and show dates in (e.g. Europe/Berlin); overrides
+    /// `timezone` in config.toml
+    #[arg(long, global = true, env = "TODO_TZ", value_name = "ZONE")]
+    pub tz: Option<Zone>,
+
+    /// Evaluate dates as if it were this date, e.g. to see what will be
+    /// overdue next monday
+    #[arg(long, global = true, value_name = "DATE")]
+    pub as_of: Option<String>,
+
+    /// Print results as a table or as JSON, JSON lines, CSV or TSV records
+    /// for scripts
+    #[arg(long, global = true, value_enum, env = "TODO_OUTPUT", default_value_t = OutputArg::Table)]
+    pub output: OutputArg,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -25,36 +51,36 @@ pub enum Commands {
         #[arg(short, long)]
         category: Option<String>,
 
-        /// Due date (YYYY-MM-DD or +ndays)
+        /// Due date, e.g. 2025-06-01, "friday 3pm", "next monday", eom, "in 2 weeks" or +3d
         #[arg(short, long)]
         due: Option<String>,
 
         /// Tags (comma-separated)
         #[arg(short, long)]
         tags: Option<String>,
-    },
-    /// List all tasks
-    List {
-        /// Filter by status
-        #[arg(short, long, value_enum)]
-        status: Option<StatusFilter>,
 
-        /// Filter by priority
-        #[arg(short, long)]
-        priority: Option<PriorityArg>,
+        /// Make this a subtask of the task with this ID
+        #[arg(long, value_name = "ID")]
+        parent: Option<u64>,
 
-        /// Filter by category
-        #[arg(short, long)]
-        category: Option<String>,
+        /// Repeat the task: daily, weekly[:mon,thu], monthly[:15] or every:<N>d
+        #[arg(long, value_name = "RULE")]
+        recur: Option<Recurrence>,
 
-        /// Show only overdue tasks
+        /// Expected effort (e.g. 2h, 30m, 1h30m or 3pts)
         #[arg(short, long)]
-        overdue: bool,
+        estimate: Option<Estimate>,
 
-        /// Show only tasks due today
-        #[arg(short, long)]
-        today: bool,
+        /// When to start working on the task (same formats as --due)
+        #[arg(long, value_name = "DATE")]
+        scheduled: Option<String>,
+
+        /// Hide the task from `list` until this date
+        #[arg(long, value_name = "DATE")]
+        wait: Option<String>,
     },
+    /// List all tasks
+    List(ListArgs),
 
     /// Edit an existing task
     Edit {
@@ -72,15 +98,91 @@ pub enum Commands {
         #[arg(short, long)]
         category: Option<String>,
 
-        /// New due date
+        /// New due date (same formats as for add; empty to clear)
         #[arg(short, long)]
         due: Option<String>,
+
+        /// New estimate (empty to clear)
+        #[arg(short, long)]
+        estimate: Option<String>,
+
+        /// New scheduled date (empty to clear)
+        #[arg(long, value_name = "DATE")]
+        scheduled: Option<String>,
+
+        /// New wait date (empty to clear)
+        #[arg(long, value_name = "DATE")]
+        wait: Option<String>,
     },
 
     /// Mark a task as completed
     Complete {
         /// The ID of the task to complete
         id: u64,
+
+        /// Also complete any pending subtasks
+        #[arg(long)]
+        cascade: bool,
+    },
+
+    /// Show every detail of a task, including its notes
+    Show {
+        /// Task ID
+        id: u64,
+    },
+
+    /// Edit a task's notes in $EDITOR
+    Note {
+        /// Task ID
+        id: u64,
+    },
+
+    /// Add a timestamped comment to a task
+    Annotate {
+        /// Task ID
+        id: u64,
+        /// The comment
+        #[arg(required = true, trailing_var_arg = true)]
+        text: Vec<String>,
+    },
+
+    /// Remove a comment from a task (the newest one if no text is given)
+    Denotate {
+        /// Task ID
+        id: u64,
+        /// The comment, or a unique part of it
+        #[arg(trailing_var_arg = true)]
+        text: Vec<String>,
+    },
+
+    /// Mark a task as in progress
+    Start {
+        /// The ID of the task to start
+        id: u64,
+    },
+
+    /// Mark a task as waiting on someone or something
+    Wait {
+        /// The ID of the task that is waiting
+        id: u64,
+    },
+
+    /// Mark a task as blocked
+    Block {
+        /// The ID of the blocked task
+        id: u64,
+    },
+
+    /// Mark a task as cancelled
+    Cancel {
+        /// The ID of the task to cancel
+        id: u64,
+    },
+
+    /// Move a task back to pending
+    Reopen {
+        /// The ID of the task to reopen
+        id: u64,
     },
 
     /// Remove a task
@@ -89,12 +191,212 @@ pub enum Commands {
         id: u64,
     },
 
+    /// Manage dependencies between tasks
+    Dep {
+        #[command(subcommand)]
+        command: DepCommands,
+    },
+
+    /// Show the most urgent tasks that can be worked on right now
+    Next {
+        /// How many tasks to show
+        #[arg(short = 'n', long, default_value_t = 5)]
+        limit: usize,
+    },
+
+    /// Find tasks by name, tags, category or notes, best matches first
+    Search {
+        /// Words to look for; each has to match, allowing one typo or gaps
+        #[arg(required = true)]
+        terms: Vec<String>,
+
+        /// Treat the terms as one case-insensitive regular expression
+        #[arg(short, long)]
+        regex: bool,
+    },
+
+    /// Track time spent on tasks
+    Track {
+        #[command(subcommand)]
+        command: TrackCommands,
+    },
+
+    /// Summaries across tasks, and saved list views run by name
+    #[command(args_conflicts_with_subcommands = true)]
+    Report {
+        /// Saved or built-in report to run; without one, list them
+        name: Option<String>,
+
+        #[command(subcommand)]
+        command: Option<ReportCommands>,
+    },
+
+    /// Revert the most recent change
+    Undo,
+
+    /// Re-apply the most recently undone change
+    Redo,
+
     Category {
         #[command(subcommand)]
         command: CategoryCommands,
     },
 }
 
+/// Which tasks `list` shows and how; `report save` stores the same options.
+#[derive(Args, Clone)]
+pub struct ListArgs {
+    /// Filter by status
+    #[arg(short, long, value_enum)]
+    pub status: Option<StatusFilter>,
+
+    /// Filter by priority
+    #[arg(short, long)]
+    pub priority: Option<PriorityArg>,
+
+    /// Filter by category
+    #[arg(short, long)]
+    pub category: Option<String>,
+
+    /// Show only overdue tasks
+    #[arg(short, long)]
+    pub overdue: bool,
+
+    /// Show only tasks due today
+    #[arg(short, long)]
+    pub today: bool,
+
+    /// Show subtasks nested under their parents
+    #[arg(long)]
+    pub tree: bool,
+
+    /// Show annotations under each task
+    #[arg(long)]
+    pub annotations: bool,
+
+    /// Show only tasks waiting on unfinished dependencies
+    #[arg(long, conflicts_with = "unblocked")]
+    pub blocked: bool,
+
+    /// Hide tasks waiting on unfinished dependencies
+    #[arg(long)]
+    pub unblocked: bool,
+
+    /// Show only tasks scheduled on this day (e.g. today, friday or jan 15)
+    #[arg(long, value_name = "DATE")]
+    pub scheduled: Option<String>,
+
+    /// Show only tasks hidden by a wait date in the future
+    #[arg(long, conflicts_with = "include_waiting")]
+    pub waiting: bool,
+
+    /// Also show tasks hidden by a wait date in the future
+    #[arg(short = 'A', long)]
+    pub include_waiting: bool,
+
+    /// Filter expression, e.g. "priority>=high and (tag:urgent or category:work) and due<eow"
+    #[arg(short, long, value_name = "EXPR")]
+    pub filter: Option<String>,
+
+    /// Sort by these fields, "-" for descending, e.g. due,-priority,category
+    #[arg(long, value_name = "FIELDS", allow_hyphen_values = true, default_value_t = SortOrder::default())]
+    pub sort: SortOrder,
+
+    /// Show one table per category, priority, status, tag or due-bucket
+    #[arg(short, long, value_name = "FIELD", conflicts_with = "tree")]
+    pub group_by: Option<GroupBy>,
+
+    /// Columns to show, e.g. id,name,due,urgency
+    #[arg(long, value_name = "COLUMNS")]
+    pub columns: Option<Columns>,
+}
+
+impl From<ListArgs> for View {
+    fn from(args: ListArgs) -> Self {
+        View {
+            description: None,
+            status: match args.status {
+                Some(StatusFilter::Pending) => Some(StatusMatch::Is(Status::Pending)),
+                Some(StatusFilter::InProgress) => Some(StatusMatch::Is(Status::InProgress)),
+                Some(StatusFilter::Waiting) => Some(StatusMatch::Is(Status::Waiting)),
+                Some(StatusFilter::Blocked) => Some(StatusMatch::Is(Status::Blocked)),
+                Some(StatusFilter::Completed) => Some(StatusMatch::Is(Status::Completed)),
+                Some(StatusFilter::Cancelled) => Some(StatusMatch::Is(Status::Cancelled)),
+                Some(StatusFilter::Open) => Some(StatusMatch::Open),
+                Some(StatusFilter::All) | None => None,
+            },
+            priority: args.priority.map(Into::into),
+            category: args.category,
+            overdue: args.overdue,
+            today: args.today,
+            blocked: args.blocked,
+            unblocked: args.unblocked,
+            scheduled: args.scheduled,
+            waiting: args.waiting,
+            include_waiting: args.include_waiting,
+            filter: args.filter,
+            sort: (args.sort != SortOrder::default()).then_some(args.sort),
+            columns: args.columns,
+            group_by: args.group_by,
+            tree: args.tree,
+            annotations: args.annotations,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum DepCommands {
+    /// Make a task depend on other tasks
+    Add {
+        /// The dependent task
+        id: u64,
+        /// Tasks that must be completed first
+        #[arg(required = true)]
+        depends_on: Vec<u64>,
+    },
+    /// Remove dependencies from a task
+    Remove {
+        /// The dependent task
+        id: u64,
+        /// Dependencies to remove
+        #[arg(required = true)]
+        depends_on: Vec<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TrackCommands {
+    /// Start a timer on a task
+    Start {
+        /// Task ID
+        id: u64,
+    },
+    /// Stop the running timer
+    Stop,
+    /// Show the running timer
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum ReportCommands {
+    /// Tracked time by task, category and tag
+    Time {
+        /// Only count time from this date on (YYYY-MM-DD or -ndays)
+        #[arg(short, long, allow_hyphen_values = true)]
+        since: Option<String>,
+    },
+    /// Estimated against actual effort per category
+    Estimates,
+    /// Save the given list options as a report
+    Save {
+        /// Name to run the report by
+        name: String,
+
+        #[command(flatten)]
+        list: ListArgs,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum CategoryCommands {
     /// List all categories
@@ -123,6 +425,47 @@ impl From<PriorityArg> for Priority {
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum StatusFilter {
     Pending,
+    InProgress,
+    Waiting,
+    Blocked,
     Completed,
+    Cancelled,
+    /// Anything not completed or cancelled
+    Open,
     All,
 }
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputArg {
+    /// Tables and messages for people
+    Table,
+    /// A JSON array of records, or one object for single-task commands
+    Json,
+    /// One JSON record per line
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+}
+
+impl From<OutputArg> for Format {
+    fn from(value: OutputArg) -> Self {
+        match value {
+            OutputArg::Table => Format::Table,
+            OutputArg::Json => Format::Json,
+            OutputArg::Jsonl => Format::Jsonl,
+            OutputArg::Csv => Format::Csv,
+            OutputArg::Tsv => Format::Tsv,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum StorageArg {
+    /// A single tasks.json file
+    Json,
+    /// A tasks.db SQLite database
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
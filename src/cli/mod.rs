@@ -1,16 +1,105 @@
-use crate::cli::args::{CategoryCommands, Cli, Commands, StatusFilter};
-use crate::core::store::TodoManager;
-use crate::core::task::Status;
+use crate::cli::args::{
+    CategoryCommands, Cli, Commands, DepCommands, OutputArg, ReportCommands, StorageArg,
+    TrackCommands,
+};
+use crate::core::config::Config;
+use crate::core::dates;
+use crate::core::group::GroupBy;
+use crate::core::journal::{Journal, JournalEntry};
+use crate::core::report::{EstimateReport, TimeReport};
+use crate::core::search::{self, Search};
+#[cfg(feature = "sqlite")]
+use crate::core::storage::SqliteStorage;
+use crate::core::storage::{JsonFileStorage, Storage, TaskQuery};
+use crate::core::store::{TaskEdits, TaskOptions, TodoManager};
+use crate::core::task::{Status, Task};
+use crate::core::time::FixedClock;
+use crate::core::view::{BUILTIN, View};
+use crate::error::TodoError;
 use crate::ui::display::{
-    display_info, display_success, display_task_table, display_welcome, PrettyTask,
+    PrettyTask, TableOptions, display_estimate_report, display_group_header, display_info,
+    display_success, display_task_detail, display_task_table, display_task_tree,
+    display_time_report, display_welcome, format_duration, group_counts,
+};
+use crate::ui::editor::edit_text;
+use crate::ui::output::{
+    CategoryRecord, EstimateRecord, Format, ReportRecord, TimeRecord, print_record, print_records,
+    print_task, print_tasks,
 };
 use anyhow::Result;
 use chrono::Utc;
+use clap::{CommandFactory, Subcommand};
 
 pub mod args;
 
 impl Cli {
+    /// Open a `TodoManager` on the storage backend selected with `--storage`.
+    /// Also sets the time zone from `--tz` or the config file, and fixes the
+    /// manager's clock when `--as-of` is given.
+    pub fn open_manager(&self) -> crate::error::Result<TodoManager> {
+        let config = Config::load(&Config::default_path()?)?;
+        let zone = match self.tz {
+            Some(zone) => zone,
+            None => config.zone()?,
+        };
+        let as_of = match &self.as_of {
+            Some(as_of) => Some(
+                dates::parse_start(as_of, Utc::now(), &zone)?
+                    .ok_or_else(|| TodoError::InvalidDate(as_of.clone()))?,
+            ),
+            None => None,
+        };
+
+        // A view only needs the tasks it shows, which some backends can
+        // pick out without loading the rest.
+        let query = self
+            .view(&config)
+            .map(|view| view.query(as_of.unwrap_or_else(Utc::now), &zone));
+        let (manager, path) = match self.storage {
+            StorageArg::Json => {
+                let path = JsonFileStorage::default_path()?;
+                (open(JsonFileStorage::new(&path), query.as_ref())?, path)
+            }
+            #[cfg(feature = "sqlite")]
+            StorageArg::Sqlite => {
+                let path = SqliteStorage::default_path()?;
+                (open(SqliteStorage::open(&path)?, query.as_ref())?, path)
+            }
+        };
+        let manager = manager
+            .with_journal(Journal::open(Journal::path_for(&path)))
+            .with_zone(zone)
+            .with_urgency(config.urgency);
+        match as_of {
+            Some(at) => Ok(manager.with_clock(FixedClock(at))),
+            None => Ok(manager),
+        }
+    }
+
+    /// The view shown by `list` or `report <name>`.
+    fn view(&self, config: &Config) -> Option<View> {
+        match &self.command {
+            Commands::List(args) => Some(View::from(args.clone())),
+            Commands::Report {
+                name: Some(name), ..
+            } => config.report(name),
+            _ => None,
+        }
+    }
+
+    /// The `--output` format asked for on a command line that failed to
+    /// parse, so the error can be reported in it.
+    pub fn requested_output() -> Format {
+        Cli::command()
+            .ignore_errors(true)
+            .try_get_matches()
+            .ok()
+            .and_then(|matches| matches.get_one::<OutputArg>("output").copied())
+            .map_or(Format::Table, Format::from)
+    }
+
     pub fn execute(self, manager: &mut TodoManager) -> Result<()> {
+        let output = Format::from(self.output);
         match self.command {
             Commands::Add {
                 name,
@@ -18,121 +107,473 @@ impl Cli {
                 category,
                 due,
                 tags,
+                parent,
+                recur,
+                estimate,
+                scheduled,
+                wait,
             } => {
                 let id = manager.add_task(
                     &name,
-                    priority,
-                    category.as_deref(),
-                    due.as_deref(),
-                    tags.as_deref(),
+                    TaskOptions {
+                        priority,
+                        category,
+                        due,
+                        tags,
+                        parent,
+                        recurrence: recur,
+                        estimate,
+                        scheduled,
+                        wait,
+                    },
                 )?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
                 display_welcome();
                 display_success(&format!("Task added successfully! (ID: {})", id))
             }
-            Commands::List {
-                status,
+            Commands::List(args) => show_view(&View::from(args), manager, output)?,
+            Commands::Edit {
+                id,
+                name,
                 priority,
                 category,
-                overdue,
-                today,
+                due,
+                estimate,
+                scheduled,
+                wait,
             } => {
-                display_welcome();
+                manager.edit_task(
+                    id,
+                    TaskEdits {
+                        name,
+                        priority: priority.map(Into::into),
+                        category,
+                        due,
+                        scheduled,
+                        wait,
+                        estimate,
+                    },
+                )?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
 
-                let mut tasks = manager.list_tasks();
+                display_welcome();
+                display_success(&format!("Task {} updated successfully!", id));
 
-                if let Some(status_filter) = status {
-                    tasks.retain(|task| match status_filter {
-                        StatusFilter::Pending => task.status == Status::Pending,
-                        StatusFilter::Completed => task.status == Status::Completed,
-                        StatusFilter::All => true,
-                    })
+                if let Some(task) = manager.get_task(id) {
+                    println!("  {}", PrettyTask(task, manager.now(), manager.zone()));
                 }
-
-                if let Some(priority_filter) = priority {
-                    let priority = priority_filter.into();
-                    tasks.retain(|task| task.priority == priority);
+            }
+            Commands::Complete { id, cascade } => {
+                let next = manager.complete_task(id, cascade)?;
+                if !output.is_table() {
+                    let tasks: Vec<&Task> = std::iter::once(id)
+                        .chain(next)
+                        .filter_map(|id| manager.get_task(id))
+                        .collect();
+                    return Ok(print_tasks(output, &tasks, manager)?);
                 }
-
-                if let Some(cat_filter) = category {
-                    tasks.retain(|task| task.category == cat_filter);
+                display_welcome();
+                display_success(&format!("Task {} marked as completed!", id));
+                for task in next.iter().filter_map(|next| manager.get_task(*next)) {
+                    display_info(&format!("Next occurrence created (ID: {})", task.id));
+                    println!("  {}", PrettyTask(task, manager.now(), manager.zone()));
                 }
-
-                if overdue {
-                    tasks.retain(|task| task.is_overdue());
+            }
+            Commands::Show { id } => {
+                let task = manager.get_task(id).ok_or(TodoError::TaskNotFound(id))?;
+                if !output.is_table() {
+                    return Ok(print_task(output, task, manager)?);
                 }
-
-                if today {
-                    tasks.retain(|task| {
-                        if let Some(due) = task.due_date {
-                            let now = Utc::now();
-                            due.date_naive() == now.date_naive()
-                        } else {
-                            false
-                        }
-                    })
+                display_welcome();
+                display_task_detail(task, manager);
+            }
+            Commands::Note { id } => {
+                let current = manager
+                    .get_task(id)
+                    .ok_or(TodoError::TaskNotFound(id))?
+                    .notes
+                    .clone();
+                // Don't lock other todo commands out while the editor is open.
+                let notes = manager.released(|| edit_text(&format!("note-{}", id), &current))??;
+                let changed = notes != current;
+                if changed {
+                    manager.set_notes(id, notes)?;
                 }
-
-                if tasks.is_empty() {
-                    display_info("No tasks found");
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
+                display_welcome();
+                if changed {
+                    display_success(&format!("Notes of task {} updated!", id));
                 } else {
-                    display_task_table(&tasks);
+                    display_info("Notes unchanged");
                 }
             }
-            Commands::Edit {
-                id,
-                name,
-                priority,
-                category,
-                due,
-            } => {
-                let priority = priority.map(|p| p.into());
-                manager.edit_task(id, name, priority, category, due)?;
-
+            Commands::Annotate { id, text } => {
+                manager.annotate(id, text.join(" "))?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
                 display_welcome();
-                display_success(&format!("Task {} updated successfully!", id));
-
-                if let Some(task) = manager.get_task(id) {
-                    println!("  {}", PrettyTask(task));
+                display_success(&format!("Annotation added to task {}", id));
+            }
+            Commands::Denotate { id, text } => {
+                let pattern = (!text.is_empty()).then(|| text.join(" "));
+                let removed = manager.denotate(id, pattern.as_deref())?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
                 }
+                display_welcome();
+                display_success(&format!("Removed annotation from task {}", id));
+                println!("  {}", removed.text);
             }
-            Commands::Complete { id } => {
-                manager.complete_task(id)?;
+            Commands::Start { id } => {
+                manager.set_status(id, Status::InProgress)?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
                 display_welcome();
-                display_success(&format!("Task {} marked as completed!", id));
+                display_success(&format!("Task {} started!", id));
+            }
+            Commands::Wait { id } => {
+                manager.set_status(id, Status::Waiting)?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
+                display_welcome();
+                display_success(&format!("Task {} is now waiting", id));
+            }
+            Commands::Block { id } => {
+                manager.set_status(id, Status::Blocked)?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
+                display_welcome();
+                display_success(&format!("Task {} marked as blocked", id));
+            }
+            Commands::Cancel { id } => {
+                manager.set_status(id, Status::Cancelled)?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
+                display_welcome();
+                display_success(&format!("Task {} cancelled", id));
+            }
+            Commands::Reopen { id } => {
+                manager.set_status(id, Status::Pending)?;
+                if !output.is_table() {
+                    return affected(output, manager, id);
+                }
+                display_welcome();
+                display_success(&format!("Task {} reopened", id));
             }
             Commands::Remove { id } => {
+                let task = manager
+                    .get_task(id)
+                    .cloned()
+                    .ok_or(TodoError::TaskNotFound(id))?;
                 manager.remove_task(id)?;
+                if !output.is_table() {
+                    return Ok(print_task(output, &task, manager)?);
+                }
                 display_welcome();
                 display_success(&format!("Task {} removed successfully!", id));
             }
-            Commands::Category { command } => {
+            Commands::Dep { command } => match command {
+                DepCommands::Add { id, depends_on } => {
+                    manager.add_dependencies(id, &depends_on)?;
+                    if !output.is_table() {
+                        return affected(output, manager, id);
+                    }
+                    display_welcome();
+                    display_success(&format!("Task {} now depends on {:?}", id, depends_on));
+                }
+                DepCommands::Remove { id, depends_on } => {
+                    manager.remove_dependencies(id, &depends_on)?;
+                    if !output.is_table() {
+                        return affected(output, manager, id);
+                    }
+                    display_welcome();
+                    display_success(&format!(
+                        "Removed dependencies {:?} from task {}",
+                        depends_on, id
+                    ));
+                }
+            },
+            Commands::Next { limit } => {
+                let mut tasks = manager.next_tasks();
+                tasks.truncate(limit);
+                if !output.is_table() {
+                    return Ok(print_tasks(output, &tasks, manager)?);
+                }
+                display_welcome();
+                if tasks.is_empty() {
+                    display_info("Nothing to do next");
+                } else {
+                    display_task_table(&tasks, manager, &TableOptions::default());
+                }
+            }
+            Commands::Search { terms, regex } => {
+                let search = if regex {
+                    Search::regex(&terms.join(" "))?
+                } else {
+                    Search::fuzzy(&terms)
+                };
+                let tasks = search::search(&manager.list_tasks(), &search);
+                if !output.is_table() {
+                    return Ok(print_tasks(output, &tasks, manager)?);
+                }
+                display_welcome();
+                if tasks.is_empty() {
+                    display_info("No matching tasks");
+                } else {
+                    let options = TableOptions {
+                        highlight: Some(search),
+                        ..TableOptions::default()
+                    };
+                    display_task_table(&tasks, manager, &options);
+                }
+            }
+            Commands::Track { command } => {
+                if !output.is_table() {
+                    let id = match command {
+                        TrackCommands::Start { id } => {
+                            manager.start_timer(id)?;
+                            id
+                        }
+                        TrackCommands::Stop => manager.stop_timer()?.0,
+                        TrackCommands::Status => match manager.running_timer() {
+                            Some(task) => task.id,
+                            None => return Ok(print_tasks(output, &[], manager)?),
+                        },
+                    };
+                    return affected(output, manager, id);
+                }
                 display_welcome();
-
                 match command {
-                    CategoryCommands::List => {
-                        let categories = manager.get_categories();
-                        if categories.is_empty() {
-                            display_info("No categories found");
-                        } else {
-                            println!("Categories:");
-                            for cat in categories {
-                                let tasks = manager.get_tasks_by_category(&cat);
-                                let completed = tasks
-                                    .iter()
-                                    .filter(|t| t.status == Status::Completed)
-                                    .count();
-                                println!(
-                                    "  • {} ({} tasks, {} completed)",
-                                    cat,
-                                    tasks.len(),
-                                    completed
-                                );
-                            }
+                    TrackCommands::Start { id } => {
+                        manager.start_timer(id)?;
+                        display_success(&format!("Timer started on task {}", id));
+                    }
+                    TrackCommands::Stop => {
+                        let (id, spent) = manager.stop_timer()?;
+                        display_success(&format!(
+                            "Timer stopped on task {} after {}",
+                            id,
+                            format_duration(spent)
+                        ));
+                    }
+                    TrackCommands::Status => match manager.running_timer() {
+                        Some(task) => {
+                            let session = task.sessions.last().unwrap();
+                            display_info(&format!(
+                                "Tracking task {} for {} (total {})",
+                                task.id,
+                                format_duration(Utc::now() - session.start),
+                                format_duration(task.tracked_time())
+                            ));
+                            println!("  {}", PrettyTask(task, manager.now(), manager.zone()));
+                        }
+                        None => display_info("No timer is running"),
+                    },
+                }
+            }
+            Commands::Report {
+                name: Some(name), ..
+            } => {
+                let config = Config::load(&Config::default_path()?)?;
+                let view = config.report(&name).ok_or(TodoError::UnknownReport(name))?;
+                show_view(&view, manager, output)?;
+            }
+            Commands::Report { command: None, .. } => {
+                let config = Config::load(&Config::default_path()?)?;
+                let mut reports: Vec<ReportRecord> = config
+                    .reports
+                    .iter()
+                    .map(|(name, view)| ReportRecord {
+                        name: name.clone(),
+                        description: view.description.clone(),
+                        builtin: false,
+                    })
+                    .collect();
+                reports.extend(
+                    BUILTIN
+                        .iter()
+                        .filter(|(name, _)| !config.reports.contains_key(*name))
+                        .map(|(name, description)| ReportRecord {
+                            name: name.to_string(),
+                            description: Some(description.to_string()),
+                            builtin: true,
+                        }),
+                );
+                if !output.is_table() {
+                    return Ok(print_records(output, &reports)?);
+                }
+                display_welcome();
+                println!("Reports:");
+                for report in &reports {
+                    match (&report.description, report.builtin) {
+                        (Some(description), true) => {
+                            println!("  • {} - {} (built-in)", report.name, description)
+                        }
+                        (Some(description), false) => {
+                            println!("  • {} - {}", report.name, description)
                         }
+                        (None, _) => println!("  • {}", report.name),
+                    }
+                }
+            }
+            Commands::Report {
+                command: Some(command),
+                ..
+            } => match command {
+                ReportCommands::Time { since } => {
+                    let since = since
+                        .map(|since| dates::parse_since(&since, manager.now(), manager.zone()))
+                        .transpose()?;
+                    let report = TimeReport::new(&manager.list_tasks(), since, manager.now());
+                    if !output.is_table() {
+                        return Ok(print_records(output, &TimeRecord::all(&report))?);
+                    }
+                    display_welcome();
+                    display_time_report(&report, manager.zone());
+                }
+                ReportCommands::Estimates => {
+                    let report = EstimateReport::new(&manager.list_tasks());
+                    if !output.is_table() {
+                        let records: Vec<EstimateRecord> = report
+                            .rows
+                            .iter()
+                            .chain([&report.total])
+                            .map(EstimateRecord::new)
+                            .collect();
+                        return Ok(print_records(output, &records)?);
+                    }
+                    display_welcome();
+                    display_estimate_report(&report);
+                }
+                ReportCommands::Save { name, list } => {
+                    // clap adds `help` itself, so it isn't among the subcommands.
+                    if ReportCommands::has_subcommand(&name) || name == "help" {
+                        return Err(TodoError::ReservedReportName(name).into());
                     }
+                    let path = Config::default_path()?;
+                    let view = View::from(list);
+                    Config::save_report(&path, &name, &view)?;
+                    if !output.is_table() {
+                        let record = ReportRecord {
+                            name,
+                            description: view.description,
+                            builtin: false,
+                        };
+                        return Ok(print_record(output, &record)?);
+                    }
+                    display_welcome();
+                    display_success(&format!("Saved report '{}' to {}", name, path.display()));
+                }
+            },
+            Commands::Undo => {
+                let entry = manager.undo()?;
+                if !output.is_table() {
+                    return changed(output, manager, &entry);
+                }
+                display_welcome();
+                display_success(&format!("Undid: {}", entry.action));
+                for change in &entry.changes {
+                    println!("  {}", change.describe());
+                }
+            }
+            Commands::Redo => {
+                let entry = manager.redo()?;
+                if !output.is_table() {
+                    return changed(output, manager, &entry);
+                }
+                display_welcome();
+                display_success(&format!("Redid: {}", entry.action));
+                for change in &entry.changes {
+                    println!("  {}", change.describe());
                 }
             }
+            Commands::Category { command } => match command {
+                CategoryCommands::List => {
+                    let tasks = manager.list_tasks();
+                    let categories = GroupBy::Category.group(&tasks, manager.now(), manager.zone());
+                    if !output.is_table() {
+                        let records: Vec<CategoryRecord> =
+                            categories.iter().map(CategoryRecord::new).collect();
+                        return Ok(print_records(output, &records)?);
+                    }
+                    display_welcome();
+                    if categories.is_empty() {
+                        display_info("No categories found");
+                    } else {
+                        println!("Categories:");
+                        for category in categories {
+                            println!("  • {} ({})", category.label, group_counts(&category));
+                        }
+                    }
+                }
+            },
         }
         Ok(())
     }
 }
+
+/// A manager holding what `query` selects, or every task without one.
+fn open(
+    storage: impl Storage + 'static,
+    query: Option<&TaskQuery>,
+) -> crate::error::Result<TodoManager> {
+    match query {
+        Some(query) => TodoManager::with_query(storage, query),
+        None => TodoManager::with_storage(storage),
+    }
+}
+
+/// Print the tasks `view` selects as a table, tree or grouped tables, or
+/// as records in another `output` format.
+fn show_view(view: &View, manager: &TodoManager, output: Format) -> Result<()> {
+    let tasks = view.tasks(manager)?;
+    if !output.is_table() {
+        return Ok(print_tasks(output, &tasks, manager)?);
+    }
+    display_welcome();
+    let options = TableOptions {
+        annotations: view.annotations,
+        columns: view.columns.clone().unwrap_or_default(),
+        ..TableOptions::default()
+    };
+    if tasks.is_empty() {
+        display_info("No tasks found");
+    } else if view.tree {
+        display_task_tree(&tasks, manager.now(), manager.zone());
+    } else if let Some(group_by) = view.group_by {
+        for group in group_by.group(&tasks, manager.now(), manager.zone()) {
+            display_group_header(&group);
+            display_task_table(&group.tasks, manager, &options);
+        }
+    } else {
+        display_task_table(&tasks, manager, &options);
+    }
+    Ok(())
+}
+
+/// Print the task a command changed.
+fn affected(output: Format, manager: &TodoManager, id: u64) -> Result<()> {
+    let task = manager.get_task(id).ok_or(TodoError::TaskNotFound(id))?;
+    Ok(print_task(output, task, manager)?)
+}
+
+/// Print the tasks an undo or redo left in place.
+fn changed(output: Format, manager: &TodoManager, entry: &JournalEntry) -> Result<()> {
+    let tasks: Vec<&Task> = entry
+        .changes
+        .iter()
+        .filter_map(|change| manager.get_task(change.id))
+        .collect();
+    Ok(print_tasks(output, &tasks, manager)?)
+}
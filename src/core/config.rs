@@ -0,0 +1,97 @@
+use crate::core::task::UrgencyCoefficients;
+use crate::core::time::Zone;
+use crate::core::view::View;
+use crate::error::{Result, TodoError};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Settings read from `config.toml` in the platform config directory
+/// (`~/.config/todolist/config.toml` on Linux). Every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// IANA zone name such as `Europe/Berlin`, or `local` for the system zone.
+    pub timezone: Option<String>,
+    pub urgency: UrgencyCoefficients,
+    /// Saved views, run with `todo report <name>`.
+    pub reports: BTreeMap<String, View>,
+}
+
+impl Config {
+    pub fn default_path() -> Result<PathBuf> {
+        let mut path = dirs::config_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Config directory not found")
+        })?;
+        path.push("todolist");
+        path.push(CONFIG_FILE);
+        Ok(path)
+    }
+
+    /// Read the config at `path`; a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(|err| TodoError::Config {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })
+    }
+
+    /// Add `view` to the config at `path` as report `name`, replacing any
+    /// report of that name and keeping the rest of the file as written.
+    pub fn save_report(path: &Path, name: &str, view: &View) -> Result<()> {
+        let invalid = |message: String| TodoError::Config {
+            path: path.display().to_string(),
+            message,
+        };
+        let text = if path.exists() {
+            fs::read_to_string(path)?
+        } else {
+            String::new()
+        };
+        let mut document: DocumentMut = text.parse().map_err(|err| invalid(format!("{}", err)))?;
+        let report: DocumentMut = toml::to_string(view)
+            .map_err(|err| invalid(err.to_string()))?
+            .parse()
+            .map_err(|err| invalid(format!("{}", err)))?;
+
+        let reports = document
+            .entry("reports")
+            .or_insert_with(|| {
+                let mut reports = Table::new();
+                reports.set_implicit(true);
+                Item::Table(reports)
+            })
+            .as_table_mut()
+            .ok_or_else(|| invalid("'reports' is not a table".to_string()))?;
+        reports.insert(name, Item::Table(report.as_table().clone()));
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, document.to_string())?;
+        Ok(())
+    }
+
+    /// The report called `name`: a saved one, or else a built-in.
+    pub fn report(&self, name: &str) -> Option<View> {
+        self.reports
+            .get(name)
+            .cloned()
+            .or_else(|| View::builtin(name))
+    }
+
+    /// The configured zone, defaulting to the system one.
+    pub fn zone(&self) -> Result<Zone> {
+        match &self.timezone {
+            Some(name) => name.parse().map_err(TodoError::InvalidTimeZone),
+            None => Ok(Zone::Local),
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Parsing of the dates typed on the command line.
+//!
+//! Besides ISO 8601 timestamps this understands a small natural language:
+//!
+//! * days: `today`, `tomorrow`, `yesterday`, `friday`, `next monday`, `sow`
+//!   and `eow` (start and end of this week, Monday and Sunday), `eom`,
+//!   `eoy`, `jan 15`, `15 jan 2026`, `2025-06-01`
+//! * offsets from today: `in 3 weeks`, `+2w`, `+1m`, `+3d`, `+3days`
+//! * a time of day before or after the day: `3pm tomorrow`,
+//!   `friday at 14:30`, `noon`
+//!
+//! Everything is relative to an explicit `now` and zone so it can be tested
+//! against a fixed clock.
+
+use crate::core::time::Zone;
+use crate::error::{Result, TodoError};
+use chrono::{
+    DateTime, Datelike, Duration, Month, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday,
+};
+
+/// Parse a due date. A bare day is due at the end of that day.
+pub fn parse_due(input: &str, now: DateTime<Utc>, zone: &Zone) -> Result<Option<DateTime<Utc>>> {
+    parse_date(
+        input,
+        NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+        now,
+        zone,
+    )
+}
+
+/// Parse a scheduled or wait date. A bare day starts at midnight.
+pub fn parse_start(input: &str, now: DateTime<Utc>, zone: &Zone) -> Result<Option<DateTime<Utc>>> {
+    parse_date(input, NaiveTime::MIN, now, zone)
+}
+
+/// Parse the start of a reporting period: a date as for `parse_start`, or
+/// `-Ndays` (N days before `now`).
+pub fn parse_since(input: &str, now: DateTime<Utc>, zone: &Zone) -> Result<DateTime<Utc>> {
+    if let Some(days) = input.strip_prefix('-')
+        && let Ok(days) = days.trim_end_matches("days").trim().parse::<i64>()
+    {
+        return Duration::try_days(days)
+            .and_then(|days| now.checked_sub_signed(days))
+            .ok_or_else(|| TodoError::InvalidDate(input.to_string()));
+    }
+    parse_start(input, now, zone)?.ok_or_else(|| TodoError::InvalidDate(input.to_string()))
+}
+
+/// A calendar day in `zone`, such as `today`, `friday` or `jan 15`.
+pub fn parse_day(input: &str, now: DateTime<Utc>, zone: &Zone) -> Option<NaiveDate> {
+    let words = words(input);
+    day(&words, zone.date_of(now))
+}
+
+const LOCAL_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+/// An empty string is `None` (used to clear a date). Otherwise accepts an
+/// ISO 8601 timestamp, with an offset or in local time, or the natural
+/// language described in the module docs. A day given without a time of
+/// day is taken at `time_of_day`.
+fn parse_date(
+    input: &str,
+    time_of_day: NaiveTime,
+    now: DateTime<Utc>,
+    zone: &Zone,
+) -> Result<Option<DateTime<Utc>>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    if let Ok(at) = DateTime::parse_from_rfc3339(input) {
+        return Ok(Some(at.with_timezone(&Utc)));
+    }
+    for format in ["%Y-%m-%dT%H:%M%#z", "%Y-%m-%d %H:%M%#z"] {
+        if let Ok(at) = DateTime::parse_from_str(input, format) {
+            return Ok(Some(at.with_timezone(&Utc)));
+        }
+    }
+    for format in LOCAL_FORMATS {
+        if let Ok(local) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok(Some(zone.resolve(local)));
+        }
+    }
+
+    let mut words = words(input);
+    let time = match words.iter().position(|word| time(word).is_some()) {
+        Some(index) => time(&words.remove(index)),
+        None => None,
+    };
+    words.retain(|word| word != "at" && word != "on");
+
+    let today = zone.date_of(now);
+    let date = match (words.is_empty(), time) {
+        (true, Some(_)) => Some(today),
+        _ => day(&words, today),
+    };
+    date.map(|date| Some(zone.resolve(date.and_time(time.unwrap_or(time_of_day)))))
+        .ok_or_else(|| TodoError::InvalidDate(input.to_string()))
+}
+
+/// Lower-cased words of `input`, with a separate `am`/`pm` joined onto the
+/// number before it (`3 pm` becomes `3pm`).
+fn words(input: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in input.to_lowercase().replace(',', " ").split_whitespace() {
+        match words.last_mut() {
+            Some(last)
+                if (word == "am" || word == "pm") && time(&format!("{last}{word}")).is_some() =>
+            {
+                last.push_str(word)
+            }
+            _ => words.push(word.to_string()),
+        }
+    }
+    words
+}
+
+/// `3pm`, `3:30pm`, `15:00`, `noon` or `midnight`. A bare number is not a
+/// time, so that `jan 15` keeps its day.
+fn time(word: &str) -> Option<NaiveTime> {
+    match word {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return Some(NaiveTime::MIN),
+        _ => {}
+    }
+    let (clock, offset) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (word, None)
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour.parse().ok()?, minute.parse().ok()?),
+        None if offset.is_some() => (clock.parse().ok()?, 0),
+        _ => return None,
+    };
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn day(words: &[String], today: NaiveDate) -> Option<NaiveDate> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    match words.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["yesterday"] => today.pred_opt(),
+        ["sow"] => Some(today - Duration::days(today.weekday().num_days_from_monday() as i64)),
+        ["eow"] => Some(today + Duration::days(6 - today.weekday().num_days_from_monday() as i64)),
+        ["eom"] => {
+            let next_month = today.with_day(1)? + Months::new(1);
+            next_month.pred_opt()
+        }
+        ["eoy"] => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        ["next", weekday] => {
+            let weekday = weekday.parse::<Weekday>().ok()?;
+            let next_monday =
+                today + Duration::days(7 - today.weekday().num_days_from_monday() as i64);
+            Some(next_monday + Duration::days(weekday.num_days_from_monday() as i64))
+        }
+        ["in", count, unit] => shift(today, count.parse().ok()?, unit),
+        [offset] if offset.starts_with('+') => {
+            let offset = &offset[1..];
+            let digits = offset
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(offset.len());
+            shift(today, offset[..digits].parse().ok()?, &offset[digits..])
+        }
+        [word] => {
+            if let Ok(weekday) = word.parse::<Weekday>() {
+                let ahead = (weekday.num_days_from_monday() + 7
+                    - today.weekday().num_days_from_monday())
+                    % 7;
+                let ahead = if ahead == 0 { 7 } else { ahead };
+                Some(today + Duration::days(ahead as i64))
+            } else {
+                NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()
+            }
+        }
+        [first, second, year @ ..] if year.len() <= 1 => {
+            let (month, day) = match (first.parse::<Month>(), second.parse::<Month>()) {
+                (Ok(month), _) => (month, second.parse::<u32>().ok()?),
+                (_, Ok(month)) => (month, first.parse::<u32>().ok()?),
+                _ => return None,
+            };
+            match year.first() {
+                Some(year) => {
+                    NaiveDate::from_ymd_opt(year.parse().ok()?, month.number_from_month(), day)
+                }
+                None => {
+                    let this_year =
+                        NaiveDate::from_ymd_opt(today.year(), month.number_from_month(), day)?;
+                    if this_year >= today {
+                        Some(this_year)
+                    } else {
+                        NaiveDate::from_ymd_opt(today.year() + 1, month.number_from_month(), day)
+                    }
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `today` moved `count` days (`d`, the default), weeks (`w`), months (`m`)
+/// or years (`y`) ahead.
+fn shift(today: NaiveDate, count: u32, unit: &str) -> Option<NaiveDate> {
+    match unit {
+        "" | "d" | "day" | "days" => today.checked_add_days(chrono::Days::new(count as u64)),
+        "w" | "wk" | "week" | "weeks" => {
+            today.checked_add_days(chrono::Days::new((count as u64).checked_mul(7)?))
+        }
+        "m" | "mo" | "month" | "months" => today.checked_add_months(Months::new(count)),
+        "y" | "yr" | "year" | "years" => {
+            today.checked_add_months(Months::new(count.checked_mul(12)?))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::{now, utc};
+    use chrono::TimeZone;
+
+    fn due(input: &str) -> DateTime<Utc> {
+        parse_due(input, now(), &utc()).unwrap().unwrap()
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, s).unwrap()
+    }
+
+    fn end_of(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        at(y, m, d, 23, 59, 59)
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(due("today"), end_of(2025, 6, 4));
+        assert_eq!(due("tomorrow"), end_of(2025, 6, 5));
+        assert_eq!(due("Yesterday"), end_of(2025, 6, 3));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(due("friday"), end_of(2025, 6, 6));
+        assert_eq!(due("fri"), end_of(2025, 6, 6));
+        assert_eq!(due("wednesday"), end_of(2025, 6, 11));
+        assert_eq!(due("monday"), end_of(2025, 6, 9));
+        assert_eq!(due("next monday"), end_of(2025, 6, 9));
+        assert_eq!(due("next friday"), end_of(2025, 6, 13));
+    }
+
+    #[test]
+    fn period_ends() {
+        assert_eq!(due("sow"), end_of(2025, 6, 2));
+        assert_eq!(due("eow"), end_of(2025, 6, 8));
+        assert_eq!(due("eom"), end_of(2025, 6, 30));
+        assert_eq!(due("eoy"), end_of(2025, 12, 31));
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(due("in 3 weeks"), end_of(2025, 6, 25));
+        assert_eq!(due("in 2 days"), end_of(2025, 6, 6));
+        assert_eq!(due("+2w"), end_of(2025, 6, 18));
+        assert_eq!(due("+1m"), end_of(2025, 7, 4));
+        assert_eq!(due("+3d"), end_of(2025, 6, 7));
+        assert_eq!(due("+3days"), end_of(2025, 6, 7));
+        assert_eq!(due("+1y"), end_of(2026, 6, 4));
+    }
+
+    #[test]
+    fn month_and_day() {
+        assert_eq!(due("jan 15"), end_of(2026, 1, 15));
+        assert_eq!(due("15 January"), end_of(2026, 1, 15));
+        assert_eq!(due("jun 4"), end_of(2025, 6, 4));
+        assert_eq!(due("dec 24"), end_of(2025, 12, 24));
+        assert_eq!(due("jan 15 2027"), end_of(2027, 1, 15));
+        assert_eq!(due("2025-07-01"), end_of(2025, 7, 1));
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(due("3pm tomorrow"), at(2025, 6, 5, 15, 0, 0));
+        assert_eq!(due("tomorrow 3 pm"), at(2025, 6, 5, 15, 0, 0));
+        assert_eq!(due("friday at 14:30"), at(2025, 6, 6, 14, 30, 0));
+        assert_eq!(due("12am jan 15"), at(2026, 1, 15, 0, 0, 0));
+        assert_eq!(due("noon"), at(2025, 6, 4, 12, 0, 0));
+        assert_eq!(due("2025-06-10 09:15"), at(2025, 6, 10, 9, 15, 0));
+    }
+
+    #[test]
+    fn iso_timestamps() {
+        assert_eq!(due("2025-06-10T14:00:00+02:00"), at(2025, 6, 10, 12, 0, 0));
+        assert_eq!(due("2025-06-10T14:00Z"), at(2025, 6, 10, 14, 0, 0));
+        assert_eq!(due("2025-06-10T14:00"), at(2025, 6, 10, 14, 0, 0));
+    }
+
+    #[test]
+    fn uses_the_given_zone() {
+        let tokyo = Zone::Named(chrono_tz::Asia::Tokyo);
+        // 10:00 UTC is already 19:00 in Tokyo, so "tomorrow" is the 5th there.
+        let due = parse_due("tomorrow 9am", now(), &tokyo).unwrap().unwrap();
+        assert_eq!(due, at(2025, 6, 5, 0, 0, 0));
+        let late = Utc.with_ymd_and_hms(2025, 6, 4, 20, 0, 0).unwrap();
+        assert_eq!(
+            parse_day("today", late, &tokyo),
+            NaiveDate::from_ymd_opt(2025, 6, 5)
+        );
+    }
+
+    #[test]
+    fn start_dates_begin_at_midnight() {
+        let start = parse_start("friday", now(), &utc()).unwrap().unwrap();
+        assert_eq!(start, at(2025, 6, 6, 0, 0, 0));
+        assert_eq!(
+            parse_since("-7days", now(), &utc()).unwrap(),
+            at(2025, 5, 28, 10, 0, 0)
+        );
+        for input in ["-99999999days", "-999999999999999days"] {
+            assert!(parse_since(input, now(), &utc()).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(parse_due("", now(), &utc()).unwrap(), None);
+        for input in [
+            "someday",
+            "13pm",
+            "jan 32",
+            "next",
+            "+2q",
+            "in three weeks",
+            "in 999999999 years",
+            "in 4294967295 weeks",
+        ] {
+            assert!(parse_due(input, now(), &utc()).is_err(), "{input}");
+        }
+    }
+}
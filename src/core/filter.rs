@@ -0,0 +1,673 @@
+//! The expression language accepted by `todo list --filter`.
+//!
+//! ```text
+//! priority>=high and (tag:urgent or category:work) and not status:completed and due<eow
+//! ```
+//!
+//! Conditions are `field op value`, combined with `and`, `or`, `not` and
+//! parentheses; `and` binds tighter than `or` and may be left out. Values
+//! containing spaces or operator characters are quoted: `due<"next monday"`.
+//!
+//! | field                       | operators    | values                          |
+//! |-----------------------------|--------------|---------------------------------|
+//! | `status`                    | `:` `=` `!=` | a status, `open` or `closed`    |
+//! | `priority`                  | all          | `low` .. `critical`             |
+//! | `category`, `tag`           | `:` `=` `!=` | a name (case-insensitive)       |
+//! | `name`                      | `:` `=` `!=` | text the name contains          |
+//! | `id`, `parent`              | all          | a task ID                       |
+//! | `due`, `scheduled`, `wait`, | all          | a day as for `--due`, or `none` |
+//! | `created`, `completed`      |              |                                 |
+//!
+//! Dates compare by calendar day in the configured zone, so `due<friday`
+//! means "due before Friday" and `due<=friday` includes Friday itself.
+
+use crate::core::dates;
+use crate::core::task::{Priority, Status, Task};
+use crate::core::time::Zone;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Condition(Condition),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusMatch {
+    Is(Status),
+    Open,
+    Closed,
+}
+
+impl FromStr for StatusMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().replace('_', "-").as_str() {
+            "pending" => StatusMatch::Is(Status::Pending),
+            "in-progress" | "started" => StatusMatch::Is(Status::InProgress),
+            "waiting" => StatusMatch::Is(Status::Waiting),
+            "blocked" => StatusMatch::Is(Status::Blocked),
+            "completed" | "done" => StatusMatch::Is(Status::Completed),
+            "cancelled" | "canceled" => StatusMatch::Is(Status::Cancelled),
+            "open" => StatusMatch::Open,
+            "closed" => StatusMatch::Closed,
+            _ => return Err(format!("unknown status '{}'", s)),
+        })
+    }
+}
+
+impl fmt::Display for StatusMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StatusMatch::Is(Status::Pending) => "pending",
+            StatusMatch::Is(Status::InProgress) => "in-progress",
+            StatusMatch::Is(Status::Waiting) => "waiting",
+            StatusMatch::Is(Status::Blocked) => "blocked",
+            StatusMatch::Is(Status::Completed) => "completed",
+            StatusMatch::Is(Status::Cancelled) => "cancelled",
+            StatusMatch::Open => "open",
+            StatusMatch::Closed => "closed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateField {
+    Due,
+    Scheduled,
+    Wait,
+    Created,
+    Completed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Status(Op, StatusMatch),
+    Priority(Op, Priority),
+    Category(Op, String),
+    Tag(Op, String),
+    Name(Op, String),
+    Id(Op, u64),
+    Parent(Op, u64),
+    /// `None` stands for "no date set".
+    Date(DateField, Op, Option<NaiveDate>),
+}
+
+/// A filter that failed to parse, with the character range of the
+/// offending token in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterError {
+    pub message: String,
+    pub input: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for FilterError {
+    /// The message, then the input with the offending token underlined.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "  {}", self.input)?;
+        write!(
+            f,
+            "  {}{}",
+            " ".repeat(self.start),
+            "^".repeat((self.end - self.start).max(1))
+        )
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl Filter {
+    /// Parse `input`, resolving relative dates such as `eow` against `now`
+    /// in `zone`.
+    pub fn parse(input: &str, now: DateTime<Utc>, zone: &Zone) -> Result<Self, FilterError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            input,
+            tokens,
+            pos: 0,
+            now,
+            zone,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(parser.error(token, "unexpected input")),
+        }
+    }
+
+    /// Whether `task` passes the filter; dates are compared by day in `zone`.
+    pub fn matches(&self, task: &Task, zone: &Zone) -> bool {
+        match self {
+            Filter::And(left, right) => left.matches(task, zone) && right.matches(task, zone),
+            Filter::Or(left, right) => left.matches(task, zone) || right.matches(task, zone),
+            Filter::Not(inner) => !inner.matches(task, zone),
+            Filter::Condition(condition) => condition.matches(task, zone),
+        }
+    }
+}
+
+impl Op {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, task: &Task, zone: &Zone) -> bool {
+        match self {
+            Condition::Status(op, status) => {
+                let is = match status {
+                    StatusMatch::Is(status) => task.status == *status,
+                    StatusMatch::Open => task.status.is_open(),
+                    StatusMatch::Closed => task.status.is_closed(),
+                };
+                is == (*op == Op::Eq)
+            }
+            Condition::Priority(op, priority) => op.test(task.priority.cmp(priority)),
+            Condition::Category(op, category) => {
+                task.category.eq_ignore_ascii_case(category) == (*op == Op::Eq)
+            }
+            Condition::Tag(op, tag) => {
+                task.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) == (*op == Op::Eq)
+            }
+            Condition::Name(op, text) => {
+                task.name.to_lowercase().contains(&text.to_lowercase()) == (*op == Op::Eq)
+            }
+            Condition::Id(op, id) => op.test(task.id.cmp(id)),
+            Condition::Parent(op, id) => task.parent_id.is_some_and(|p| op.test(p.cmp(id))),
+            Condition::Date(field, op, day) => {
+                let date = match field {
+                    DateField::Due => task.due_date,
+                    DateField::Scheduled => task.scheduled,
+                    DateField::Wait => task.wait_until,
+                    DateField::Created => Some(task.create_at),
+                    DateField::Completed => task.completed_at,
+                };
+                match (date, day) {
+                    (Some(date), Some(day)) => op.test(zone.date_of(date).cmp(day)),
+                    (date, None) => date.is_none() == (*op == Op::Eq),
+                    (None, Some(_)) => false,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    LParen,
+    RParen,
+    Op(Op),
+    Word(String),
+    /// A quoted value; never a keyword.
+    Quoted(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        let kind = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '(' {
+            i += 1;
+            Kind::LParen
+        } else if c == ')' {
+            i += 1;
+            Kind::RParen
+        } else if let Some(op) = match two.as_str() {
+            "!=" => Some(Op::Ne),
+            "<=" => Some(Op::Le),
+            ">=" => Some(Op::Ge),
+            _ => None,
+        } {
+            i += 2;
+            Kind::Op(op)
+        } else if let Some(op) = match c {
+            ':' | '=' => Some(Op::Eq),
+            '<' => Some(Op::Lt),
+            '>' => Some(Op::Gt),
+            _ => None,
+        } {
+            i += 1;
+            Kind::Op(op)
+        } else if c == '"' || c == '\'' {
+            let close = chars[i + 1..]
+                .iter()
+                .position(|&q| q == c)
+                .ok_or_else(|| error(input, start, chars.len(), "unterminated quoted value"))?;
+            let value = chars[i + 1..i + 1 + close].iter().collect();
+            i += close + 2;
+            Kind::Quoted(value)
+        } else {
+            while i < chars.len() && !chars[i].is_whitespace() && !"():=<>!\"'".contains(chars[i]) {
+                i += 1;
+            }
+            if i == start {
+                return Err(error(input, start, start + 1, "unexpected character"));
+            }
+            Kind::Word(chars[start..i].iter().collect())
+        };
+        tokens.push(Token {
+            kind,
+            start,
+            end: i,
+        });
+    }
+    Ok(tokens)
+}
+
+fn error(input: &str, start: usize, end: usize, message: impl Into<String>) -> FilterError {
+    FilterError {
+        message: message.into(),
+        input: input.to_string(),
+        start,
+        end,
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    now: DateTime<Utc>,
+    zone: &'a Zone,
+}
+
+const FIELDS: &str =
+    "status, priority, category, tag, name, id, parent, due, scheduled, wait, created or completed";
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, token: &Token, message: impl Into<String>) -> FilterError {
+        error(self.input, token.start, token.end, message)
+    }
+
+    fn at_end(&self) -> FilterError {
+        let end = self.input.chars().count();
+        error(self.input, end, end + 1, "unexpected end of filter")
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: Kind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterError> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            self.pos += 1;
+            left = Filter::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    /// Conditions joined by `and`, or by nothing at all.
+    fn and(&mut self) -> Result<Filter, FilterError> {
+        let mut left = self.not()?;
+        loop {
+            if self.keyword("and") {
+                self.pos += 1;
+            } else if self.keyword("or")
+                || matches!(
+                    self.peek(),
+                    None | Some(Token {
+                        kind: Kind::RParen,
+                        ..
+                    })
+                )
+            {
+                return Ok(left);
+            }
+            left = Filter::And(Box::new(left), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Filter, FilterError> {
+        if self.keyword("not") {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Filter, FilterError> {
+        let token = self.next().ok_or_else(|| self.at_end())?;
+        match &token.kind {
+            Kind::LParen => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token {
+                        kind: Kind::RParen, ..
+                    }) => Ok(inner),
+                    Some(other) => Err(self.error(&other, "expected ')'")),
+                    None => Err(self.error(&token, "unclosed '('")),
+                }
+            }
+            Kind::Word(field) => {
+                let op = match self.next() {
+                    Some(Token {
+                        kind: Kind::Op(op), ..
+                    }) => op,
+                    Some(other) => {
+                        return Err(self.error(
+                            &other,
+                            format!("expected an operator such as ':' after '{}'", field),
+                        ));
+                    }
+                    None => return Err(self.at_end()),
+                };
+                let value = self.next().ok_or_else(|| self.at_end())?;
+                let text = match &value.kind {
+                    Kind::Word(text) | Kind::Quoted(text) => text.clone(),
+                    _ => return Err(self.error(&value, "expected a value")),
+                };
+                self.condition(&token, field, op, &value, &text)
+                    .map(Filter::Condition)
+            }
+            _ => Err(self.error(&token, "expected a condition such as 'status:pending'")),
+        }
+    }
+
+    fn condition(
+        &self,
+        field_token: &Token,
+        field: &str,
+        op: Op,
+        value: &Token,
+        text: &str,
+    ) -> Result<Condition, FilterError> {
+        let field = field.to_lowercase();
+        let equality_only = |condition: Condition| match op {
+            Op::Eq | Op::Ne => Ok(condition),
+            _ => Err(self.error(
+                value,
+                format!("'{}' can only be compared with ':', '=' or '!='", field),
+            )),
+        };
+        match field.as_str() {
+            "status" => {
+                let status = text.parse().map_err(|message| self.error(value, message))?;
+                equality_only(Condition::Status(op, status))
+            }
+            "priority" => text
+                .parse()
+                .map(|priority| Condition::Priority(op, priority))
+                .map_err(|_| {
+                    self.error(
+                        value,
+                        format!(
+                            "unknown priority '{}' (expected low, medium, high or critical)",
+                            text
+                        ),
+                    )
+                }),
+            "category" | "cat" => equality_only(Condition::Category(op, text.to_string())),
+            "tag" | "tags" => equality_only(Condition::Tag(op, text.to_string())),
+            "name" => equality_only(Condition::Name(op, text.to_string())),
+            "id" | "parent" => {
+                let id = text
+                    .trim_start_matches('#')
+                    .parse()
+                    .map_err(|_| self.error(value, format!("'{}' is not a task ID", text)))?;
+                Ok(if field == "id" {
+                    Condition::Id(op, id)
+                } else {
+                    Condition::Parent(op, id)
+                })
+            }
+            "due" | "scheduled" | "wait" | "created" | "completed" => {
+                let field = match field.as_str() {
+                    "due" => DateField::Due,
+                    "scheduled" => DateField::Scheduled,
+                    "wait" => DateField::Wait,
+                    "created" => DateField::Created,
+                    _ => DateField::Completed,
+                };
+                if text.eq_ignore_ascii_case("none") {
+                    return equality_only(Condition::Date(field, op, None));
+                }
+                dates::parse_day(text, self.now, self.zone)
+                    .map(|day| Condition::Date(field, op, Some(day)))
+                    .ok_or_else(|| self.error(value, format!("'{}' is not a date", text)))
+            }
+            _ => Err(self.error(
+                field_token,
+                format!("unknown field '{}' (expected {})", field, FIELDS),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::{now, utc};
+    use chrono::TimeZone;
+
+    fn parse(input: &str) -> Filter {
+        Filter::parse(input, now(), &utc()).unwrap()
+    }
+
+    fn parse_err(input: &str) -> FilterError {
+        Filter::parse(input, now(), &utc()).unwrap_err()
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    fn status(status: &str) -> Filter {
+        Filter::Condition(Condition::Status(Op::Eq, status.parse().unwrap()))
+    }
+
+    fn tag(tag: &str) -> Filter {
+        Filter::Condition(Condition::Tag(Op::Eq, tag.to_string()))
+    }
+
+    fn and(left: Filter, right: Filter) -> Filter {
+        Filter::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Filter, right: Filter) -> Filter {
+        Filter::Or(Box::new(left), Box::new(right))
+    }
+
+    /// A pending task due at the end of `due` (UTC), if given.
+    fn task(priority: Priority, category: &str, tags: &[&str], due: Option<u32>) -> Task {
+        let mut task = Task::new(1, "task".to_string());
+        task.set_priority(priority);
+        task.set_category(category.to_string());
+        for tag in tags {
+            task.add_tag(tag.to_string());
+        }
+        if let Some(d) = due {
+            task.set_due_date(Utc.with_ymd_and_hms(2025, 6, d, 23, 59, 59).unwrap());
+        }
+        task
+    }
+
+    #[test]
+    fn example_expression() {
+        let filter = parse(
+            "priority>=high and (tag:urgent or category:work) and not status:completed and due<eow",
+        );
+        let matches = |task: &Task| filter.matches(task, &utc());
+
+        assert!(matches(&task(Priority::High, "work", &[], Some(6))));
+        assert!(matches(&task(
+            Priority::Critical,
+            "home",
+            &["urgent"],
+            Some(7)
+        )));
+        assert!(!matches(&task(Priority::Medium, "work", &[], Some(6))));
+        assert!(!matches(&task(Priority::High, "home", &[], Some(6))));
+        // Due on Sunday, the end of the week itself.
+        assert!(!matches(&task(Priority::High, "work", &[], Some(8))));
+        assert!(!matches(&task(Priority::High, "work", &[], None)));
+
+        let mut done = task(Priority::High, "work", &[], Some(6));
+        done.complete();
+        assert!(!matches(&done));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("tag:a or tag:b and tag:c"),
+            or(tag("a"), and(tag("b"), tag("c")))
+        );
+        assert_eq!(
+            parse("tag:a and tag:b or tag:c"),
+            or(and(tag("a"), tag("b")), tag("c"))
+        );
+        assert_eq!(
+            parse("(tag:a or tag:b) and tag:c"),
+            and(or(tag("a"), tag("b")), tag("c"))
+        );
+    }
+
+    #[test]
+    fn and_may_be_left_out() {
+        assert_eq!(parse("tag:a tag:b"), parse("tag:a and tag:b"));
+        assert_eq!(
+            parse("status:open tag:a or tag:b"),
+            or(and(status("open"), tag("a")), tag("b"))
+        );
+    }
+
+    #[test]
+    fn not_applies_to_the_next_condition() {
+        assert_eq!(
+            parse("not tag:a and tag:b"),
+            and(Filter::Not(Box::new(tag("a"))), tag("b"))
+        );
+        assert_eq!(
+            parse("NOT not tag:a"),
+            Filter::Not(Box::new(Filter::Not(Box::new(tag("a")))))
+        );
+        let filter = parse("not tag:a");
+        assert!(filter.matches(&task(Priority::Low, "x", &["b"], None), &utc()));
+        assert!(!filter.matches(&task(Priority::Low, "x", &["A"], None), &utc()));
+    }
+
+    #[test]
+    fn quoted_dates() {
+        assert_eq!(
+            parse("due<\"next monday\""),
+            Filter::Condition(Condition::Date(DateField::Due, Op::Lt, day(2025, 6, 9)))
+        );
+        assert_eq!(
+            parse("scheduled>='jun 10'"),
+            Filter::Condition(Condition::Date(
+                DateField::Scheduled,
+                Op::Ge,
+                day(2025, 6, 10)
+            ))
+        );
+    }
+
+    #[test]
+    fn dates_compare_by_day() {
+        let filter = parse("due<=friday");
+        assert!(filter.matches(&task(Priority::Low, "x", &[], Some(6)), &utc()));
+        assert!(!filter.matches(&task(Priority::Low, "x", &[], Some(7)), &utc()));
+
+        // 23:59 UTC on Friday is already Saturday in Tokyo.
+        let tokyo = Zone::Named(chrono_tz::Asia::Tokyo);
+        assert!(!filter.matches(&task(Priority::Low, "x", &[], Some(6)), &tokyo));
+    }
+
+    #[test]
+    fn none_matches_missing_dates() {
+        let without = task(Priority::Low, "x", &[], None);
+        let with = task(Priority::Low, "x", &[], Some(6));
+
+        let filter = parse("due:none");
+        assert!(filter.matches(&without, &utc()));
+        assert!(!filter.matches(&with, &utc()));
+
+        let filter = parse("due!=none");
+        assert!(!filter.matches(&without, &utc()));
+        assert!(filter.matches(&with, &utc()));
+
+        assert!(parse("wait:NONE").matches(&with, &utc()));
+        assert_eq!(parse_err("due<none").start, 4);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let err = parse_err("status:open and colour:red");
+        assert!(err.message.starts_with("unknown field 'colour'"));
+        assert_eq!((err.start, err.end), (16, 22));
+
+        let err = parse_err("priority>urgent");
+        assert!(err.message.starts_with("unknown priority 'urgent'"));
+        assert_eq!((err.start, err.end), (9, 15));
+
+        let err = parse_err("due<someday");
+        assert_eq!(err.message, "'someday' is not a date");
+        assert_eq!((err.start, err.end), (4, 11));
+
+        let err = parse_err("tag:a and (tag:b or tag:c");
+        assert_eq!(err.message, "unclosed '('");
+        assert_eq!((err.start, err.end), (10, 11));
+
+        let err = parse_err("tag:a)");
+        assert_eq!(err.message, "unexpected input");
+        assert_eq!(err.start, 5);
+
+        let err = parse_err("name:\"milk");
+        assert_eq!(err.message, "unterminated quoted value");
+        assert_eq!(err.start, 5);
+    }
+}
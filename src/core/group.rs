@@ -0,0 +1,138 @@
+use crate::core::sort::status_rank;
+use crate::core::task::{Priority, Status, Task};
+use crate::core::time::Zone;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// What `list --group-by` splits tasks by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Category,
+    Priority,
+    Status,
+    /// A task with several tags shows up under each of them.
+    Tag,
+    /// Overdue, today, tomorrow, the next seven days, later, or no due date.
+    /// Closed tasks are never overdue; those due before today are "earlier".
+    DueBucket,
+}
+
+const GROUPINGS: &[(&str, GroupBy)] = &[
+    ("category", GroupBy::Category),
+    ("priority", GroupBy::Priority),
+    ("status", GroupBy::Status),
+    ("tag", GroupBy::Tag),
+    ("due-bucket", GroupBy::DueBucket),
+];
+
+/// Tasks sharing a value of the grouped-by field.
+#[derive(Debug)]
+pub struct Group<'a> {
+    pub label: String,
+    pub tasks: Vec<&'a Task>,
+}
+
+impl Group<'_> {
+    pub fn completed(&self) -> usize {
+        self.tasks
+            .iter()
+            .filter(|task| task.status == Status::Completed)
+            .count()
+    }
+}
+
+/// Position and label of a task's group. The position orders the groups:
+/// most important priority first, statuses in work-queue order, due
+/// buckets from overdue to none, and names alphabetically.
+type Key = ((u8, String), String);
+
+impl GroupBy {
+    /// Split `tasks` into groups, keeping their order within each group.
+    pub fn group<'a>(self, tasks: &[&'a Task], now: DateTime<Utc>, zone: &Zone) -> Vec<Group<'a>> {
+        let mut groups: Vec<(Key, Group<'a>)> = Vec::new();
+        for task in tasks {
+            for key in self.keys(task, now, zone) {
+                match groups.iter_mut().find(|(existing, _)| *existing == key) {
+                    Some((_, group)) => group.tasks.push(task),
+                    None => {
+                        let label = key.1.clone();
+                        groups.push((
+                            key,
+                            Group {
+                                label,
+                                tasks: vec![task],
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+        groups.sort_by(|a, b| a.0.0.cmp(&b.0.0));
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+
+    fn keys(self, task: &Task, now: DateTime<Utc>, zone: &Zone) -> Vec<Key> {
+        fn named(name: &str) -> Key {
+            ((0, name.to_lowercase()), name.to_string())
+        }
+        fn ranked(rank: u8, label: impl ToString) -> Key {
+            ((rank, String::new()), label.to_string())
+        }
+
+        match self {
+            GroupBy::Category => vec![named(&task.category)],
+            GroupBy::Priority => {
+                let rank = match task.priority {
+                    Priority::Critical => 0,
+                    Priority::High => 1,
+                    Priority::Medium => 2,
+                    Priority::Low => 3,
+                };
+                vec![ranked(rank, &task.priority)]
+            }
+            GroupBy::Status => vec![ranked(status_rank(&task.status), &task.status)],
+            GroupBy::Tag if task.tags.is_empty() => vec![ranked(1, "No tag")],
+            GroupBy::Tag => task.tags.iter().map(|tag| named(tag)).collect(),
+            GroupBy::DueBucket => vec![match task.days_until_due(now, zone) {
+                _ if task.is_overdue(now) => ranked(0, "Overdue"),
+                Some(days) if days < 0 => ranked(1, "Earlier"),
+                Some(0) => ranked(2, "Today"),
+                Some(1) => ranked(3, "Tomorrow"),
+                Some(days) if days <= 7 => ranked(4, "Next 7 days"),
+                Some(_) => ranked(5, "Later"),
+                None => ranked(6, "No due date"),
+            }],
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        GROUPINGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, group_by)| *group_by)
+            .ok_or_else(|| {
+                let names: Vec<&str> = GROUPINGS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Unknown grouping '{}' (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = GROUPINGS
+            .iter()
+            .find(|(_, group_by)| group_by == self)
+            .map_or("?", |(name, _)| *name);
+        write!(f, "{}", name)
+    }
+}
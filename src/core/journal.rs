@@ -0,0 +1,232 @@
+use crate::core::task::Task;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The state of one task before and after an operation. `None` means the
+/// task did not exist on that side of the change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub id: u64,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+impl Change {
+    pub fn new(id: u64, before: Option<Task>, after: Option<Task>) -> Self {
+        Self { id, before, after }
+    }
+
+    /// The same change, played backwards.
+    pub fn inverse(&self) -> Self {
+        Self {
+            id: self.id,
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+
+    /// Names of the task fields that differ between `before` and `after`.
+    pub fn changed_fields(&self) -> Vec<String> {
+        let (Some(before), Some(after)) = (&self.before, &self.after) else {
+            return Vec::new();
+        };
+        let (Ok(Value::Object(before)), Ok(Value::Object(after))) =
+            (serde_json::to_value(before), serde_json::to_value(after))
+        else {
+            return Vec::new();
+        };
+        after
+            .iter()
+            .filter(|(key, value)| key.as_str() != "update_at" && before.get(*key) != Some(value))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// One-line, human-readable summary such as `#3 Buy milk: status, completed_at`.
+    pub fn describe(&self) -> String {
+        match (&self.before, &self.after) {
+            (None, Some(task)) => format!("#{} {}: added", self.id, task.name),
+            (Some(task), None) => format!("#{} {}: removed", self.id, task.name),
+            (Some(_), Some(task)) => {
+                let fields = self.changed_fields();
+                if fields.is_empty() {
+                    format!("#{} {}: unchanged", self.id, task.name)
+                } else {
+                    format!("#{} {}: {}", self.id, task.name, fields.join(", "))
+                }
+            }
+            (None, None) => format!("#{}: unchanged", self.id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntryKind {
+    /// A regular mutation made through `TodoManager`.
+    Apply,
+    /// Reverted the entry with sequence number `target`.
+    Undo { target: u64 },
+    /// Re-applied the entry with sequence number `target`.
+    Redo { target: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub action: String,
+    #[serde(flatten)]
+    pub kind: EntryKind,
+    /// The changes this entry made, in the order they were applied.
+    pub changes: Vec<Change>,
+}
+
+/// Append-only log of every mutation, used to undo and redo them.
+///
+/// Undo and redo are themselves appended as entries, so the file is never
+/// rewritten; the undo and redo stacks are rebuilt by replaying it. The
+/// file is only read once an entry is needed, so commands that change
+/// nothing never parse it.
+#[derive(Debug)]
+pub struct Journal {
+    path: Option<PathBuf>,
+    /// `None` until the file has been read.
+    entries: Option<Vec<JournalEntry>>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self {
+            path: None,
+            entries: Some(Vec::new()),
+        }
+    }
+}
+
+impl Journal {
+    /// A journal that is not persisted anywhere.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Open (or start) the journal file at `path`. Nothing is read until
+    /// the entries are first needed.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            entries: None,
+        }
+    }
+
+    /// Forget the entries read so far, so the next use picks up entries
+    /// other processes added.
+    pub fn reload(&mut self) {
+        if self.path.is_some() {
+            self.entries = None;
+        }
+    }
+
+    /// `<data>.journal.jsonl` next to the data file `data`, so each storage
+    /// backend keeps its own history.
+    pub fn path_for(data: &Path) -> PathBuf {
+        let mut name = data.file_name().unwrap_or_default().to_os_string();
+        name.push(".journal.jsonl");
+        data.with_file_name(name)
+    }
+
+    /// The entries, reading the file first if it hasn't been read yet.
+    fn read(&mut self) -> Result<&mut Vec<JournalEntry>> {
+        if self.entries.is_none() {
+            let mut entries = Vec::new();
+            if let Some(path) = self.path.as_ref().filter(|path| path.exists()) {
+                for line in fs::read_to_string(path)?.lines() {
+                    if !line.trim().is_empty() {
+                        entries.push(serde_json::from_str(line)?);
+                    }
+                }
+            }
+            self.entries = Some(entries);
+        }
+        Ok(self.entries.get_or_insert_default())
+    }
+
+    pub fn entries(&mut self) -> Result<&[JournalEntry]> {
+        Ok(self.read()?)
+    }
+
+    pub fn get(&mut self, seq: u64) -> Result<Option<&JournalEntry>> {
+        Ok(self.read()?.iter().find(|entry| entry.seq == seq))
+    }
+
+    pub fn append(
+        &mut self,
+        action: String,
+        kind: EntryKind,
+        changes: Vec<Change>,
+    ) -> Result<&JournalEntry> {
+        let entry = JournalEntry {
+            seq: self.read()?.last().map_or(1, |entry| entry.seq + 1),
+            at: Utc::now(),
+            action,
+            kind,
+            changes,
+        };
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+            file.sync_data()?;
+        }
+        let entries = self.read()?;
+        entries.push(entry);
+        Ok(entries.last().unwrap())
+    }
+
+    fn stacks(entries: &[JournalEntry]) -> (Vec<u64>, Vec<u64>) {
+        let mut undo = Vec::new();
+        let mut redo = Vec::new();
+        for entry in entries {
+            match entry.kind {
+                EntryKind::Apply => {
+                    undo.push(entry.seq);
+                    redo.clear();
+                }
+                EntryKind::Undo { target } => {
+                    undo.retain(|seq| *seq != target);
+                    redo.push(target);
+                }
+                EntryKind::Redo { target } => {
+                    redo.retain(|seq| *seq != target);
+                    undo.push(target);
+                }
+            }
+        }
+        (undo, redo)
+    }
+
+    /// The entry the next undo would revert.
+    pub fn next_undo(&mut self) -> Result<Option<&JournalEntry>> {
+        let seq = Self::stacks(self.read()?).0.last().copied();
+        Ok(match seq {
+            Some(seq) => self.get(seq)?,
+            None => None,
+        })
+    }
+
+    /// The entry the next redo would re-apply.
+    pub fn next_redo(&mut self) -> Result<Option<&JournalEntry>> {
+        let seq = Self::stacks(self.read()?).1.last().copied();
+        Ok(match seq {
+            Some(seq) => self.get(seq)?,
+            None => None,
+        })
+    }
+}
@@ -1,2 +1,16 @@
+pub mod config;
+pub mod dates;
+pub mod filter;
+pub mod group;
+pub mod journal;
+pub mod recurrence;
+pub mod report;
+pub mod search;
+pub mod sort;
+pub mod storage;
 pub mod store;
-pub mod task;
\ No newline at end of file
+pub mod task;
+#[cfg(test)]
+mod testing;
+pub mod time;
+pub mod view;
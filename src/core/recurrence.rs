@@ -0,0 +1,232 @@
+use crate::core::time::Zone;
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDateTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest gap `every:<N>d` accepts: about a hundred years.
+const MAX_DAYS: u32 = 36_500;
+
+/// How a task repeats once it is completed.
+///
+/// Written and parsed as `daily`, `weekly[:mon,thu]`, `monthly[:15]` or
+/// `every:<N>d` (N days after each completion).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    /// On the given weekdays; an empty list repeats on the due date's weekday.
+    Weekly {
+        weekdays: Vec<Weekday>,
+    },
+    /// On day N of each month, clamped to the month's length. `None` keeps
+    /// the due date's day.
+    Monthly {
+        day: Option<u32>,
+    },
+    /// N days after the task is completed, regardless of its due date.
+    AfterCompletion {
+        days: u32,
+    },
+}
+
+impl Recurrence {
+    /// The due date of the occurrence that follows one due at `due` and
+    /// completed at `completed_at`. Schedule-based rules skip occurrences
+    /// that are already in the past. Days are stepped in the configured
+    /// zone, so the wall-clock due time survives DST changes.
+    ///
+    /// `None` if that date is past the end of the calendar chrono can
+    /// represent, which ends the series.
+    pub fn next_due(
+        &self,
+        due: Option<DateTime<Utc>>,
+        completed_at: DateTime<Utc>,
+        zone: &Zone,
+    ) -> Option<DateTime<Utc>> {
+        let base = zone.localize(due.unwrap_or(completed_at));
+        let completed = zone.localize(completed_at);
+        let next = match self {
+            Recurrence::AfterCompletion { days } => {
+                completed.checked_add_days(Days::new(*days as u64))?
+            }
+            _ => {
+                let mut next = self.step(base, base)?;
+                while next <= completed {
+                    next = self.step(next, base)?;
+                }
+                next
+            }
+        };
+        // Leave a day of room, so resolving a skipped wall-clock time can't
+        // step off the end either.
+        next.checked_add_signed(Duration::days(1))?;
+        Some(zone.resolve(next))
+    }
+
+    fn step(&self, from: NaiveDateTime, anchor: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Recurrence::Daily => from.checked_add_days(Days::new(1)),
+            Recurrence::Weekly { weekdays } if weekdays.is_empty() => {
+                from.checked_add_days(Days::new(7))
+            }
+            Recurrence::Weekly { weekdays } => (1..=7)
+                .map_while(|days| from.checked_add_days(Days::new(days)))
+                .find(|date| weekdays.contains(&date.weekday())),
+            Recurrence::Monthly { day } => {
+                let day = day.unwrap_or(anchor.day());
+                // Day N of `from`'s own month if that is still ahead,
+                // otherwise of the month after.
+                let this_month = on_day(from, day)?;
+                if this_month > from {
+                    return Some(this_month);
+                }
+                let next_month = from.with_day(1)?.checked_add_months(Months::new(1))?;
+                on_day(next_month, day)
+            }
+            Recurrence::AfterCompletion { days } => from.checked_add_days(Days::new(*days as u64)),
+        }
+    }
+}
+
+/// `date` moved to day `day` of its month, or to the month's last day if it
+/// is shorter.
+fn on_day(date: NaiveDateTime, day: u32) -> Option<NaiveDateTime> {
+    (1..=day).rev().find_map(|day| date.with_day(day))
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly { weekdays } if weekdays.is_empty() => write!(f, "weekly"),
+            Recurrence::Weekly { weekdays } => {
+                let days: Vec<String> = weekdays
+                    .iter()
+                    .map(|day| day.to_string().to_lowercase())
+                    .collect();
+                write!(f, "weekly:{}", days.join(","))
+            }
+            Recurrence::Monthly { day: None } => write!(f, "monthly"),
+            Recurrence::Monthly { day: Some(day) } => write!(f, "monthly:{}", day),
+            Recurrence::AfterCompletion { days } => write!(f, "every:{}d", days),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg.trim())),
+            None => (s.as_str(), None),
+        };
+        match (kind, arg) {
+            ("daily", None) => Ok(Recurrence::Daily),
+            ("weekly", None) => Ok(Recurrence::Weekly { weekdays: vec![] }),
+            ("weekly", Some(days)) => {
+                let weekdays = days
+                    .split(',')
+                    .map(|day| {
+                        day.trim()
+                            .parse::<Weekday>()
+                            .map_err(|_| format!("Invalid weekday: {}", day))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Recurrence::Weekly { weekdays })
+            }
+            ("monthly", None) => Ok(Recurrence::Monthly { day: None }),
+            ("monthly", Some(day)) => match day.parse::<u32>() {
+                Ok(day @ 1..=31) => Ok(Recurrence::Monthly { day: Some(day) }),
+                _ => Err(format!("Invalid day of month: {}", day)),
+            },
+            ("every", Some(days)) => match days.trim_end_matches('d').parse::<u32>() {
+                Ok(days @ 1..=MAX_DAYS) => Ok(Recurrence::AfterCompletion { days }),
+                _ => Err(format!(
+                    "Invalid number of days: {} (expected 1 to {})",
+                    days, MAX_DAYS
+                )),
+            },
+            _ => Err(format!(
+                "Invalid recurrence '{}' (expected daily, weekly[:mon,...], monthly[:N] or every:Nd)",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::utc;
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, 9, 0, 0).unwrap()
+    }
+
+    /// Due dates of the occurrences after one due at `due`, each completed
+    /// on the day it is due.
+    fn series(rule: &str, due: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        let rule: Recurrence = rule.parse().unwrap();
+        std::iter::successors(Some(due), |due| rule.next_due(Some(*due), *due, &utc()))
+            .skip(1)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn weekly_on_weekdays() {
+        // Wednesday, 4 June 2025.
+        assert_eq!(
+            series("weekly:mon,thu", at(2025, 6, 4), 3),
+            vec![at(2025, 6, 5), at(2025, 6, 9), at(2025, 6, 12)]
+        );
+        assert_eq!(
+            series("weekly", at(2025, 6, 4), 2),
+            vec![at(2025, 6, 11), at(2025, 6, 18)]
+        );
+    }
+
+    #[test]
+    fn monthly_clamps_to_short_months() {
+        assert_eq!(
+            series("monthly:31", at(2025, 1, 31), 3),
+            vec![at(2025, 2, 28), at(2025, 3, 31), at(2025, 4, 30)]
+        );
+    }
+
+    #[test]
+    fn monthly_day_still_ahead_this_month() {
+        assert_eq!(
+            series("monthly:25", at(2025, 10, 17), 1),
+            vec![at(2025, 10, 25)]
+        );
+        assert_eq!(
+            series("monthly:17", at(2025, 10, 17), 1),
+            vec![at(2025, 11, 17)]
+        );
+        assert_eq!(
+            series("monthly:5", at(2025, 10, 17), 1),
+            vec![at(2025, 11, 5)]
+        );
+    }
+
+    #[test]
+    fn skips_occurrences_already_past() {
+        let rule = Recurrence::Daily;
+        let next = rule.next_due(Some(at(2025, 6, 1)), at(2025, 6, 4), &utc());
+        assert_eq!(next, Some(at(2025, 6, 5)));
+    }
+
+    #[test]
+    fn dates_past_the_calendar_end_the_series() {
+        let last = DateTime::<Utc>::MAX_UTC - Duration::hours(1);
+        assert_eq!(Recurrence::Daily.next_due(Some(last), last, &utc()), None);
+        let every = Recurrence::AfterCompletion { days: MAX_DAYS };
+        assert_eq!(every.next_due(None, last, &utc()), None);
+        assert!("every:100000000d".parse::<Recurrence>().is_err());
+    }
+}
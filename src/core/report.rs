@@ -0,0 +1,143 @@
+use crate::core::task::{Estimate, Status, Task};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+/// Tracked time over a period, totalled per task, category and tag.
+#[derive(Debug, Clone)]
+pub struct TimeReport {
+    pub since: Option<DateTime<Utc>>,
+    pub until: DateTime<Utc>,
+    /// `(task id, task name, time)`, longest first.
+    pub by_task: Vec<(u64, String, Duration)>,
+    pub by_category: Vec<(String, Duration)>,
+    /// A task with several tags counts toward each of them.
+    pub by_tag: Vec<(String, Duration)>,
+    pub total: Duration,
+}
+
+impl TimeReport {
+    /// Sum the time tracked on `tasks` between `since` (or the beginning)
+    /// and `until`. Tasks without time in the period are left out.
+    pub fn new(tasks: &[&Task], since: Option<DateTime<Utc>>, until: DateTime<Utc>) -> Self {
+        let from = since.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let mut by_task = Vec::new();
+        let mut by_category: BTreeMap<String, Duration> = BTreeMap::new();
+        let mut by_tag: BTreeMap<String, Duration> = BTreeMap::new();
+        let mut total = Duration::zero();
+
+        for task in tasks {
+            let spent = task.tracked_between(from, until);
+            if spent.is_zero() {
+                continue;
+            }
+            by_task.push((task.id, task.name.clone(), spent));
+            *by_category
+                .entry(task.category.clone())
+                .or_insert_with(Duration::zero) += spent;
+            for tag in &task.tags {
+                *by_tag.entry(tag.clone()).or_insert_with(Duration::zero) += spent;
+            }
+            total += spent;
+        }
+
+        Self {
+            since,
+            until,
+            by_task: sorted_by_time(by_task, |(_, _, spent)| *spent),
+            by_category: sorted_by_time(by_category.into_iter().collect(), |(_, spent)| *spent),
+            by_tag: sorted_by_time(by_tag.into_iter().collect(), |(_, spent)| *spent),
+            total,
+        }
+    }
+}
+
+fn sorted_by_time<T>(mut rows: Vec<T>, time: impl Fn(&T) -> Duration) -> Vec<T> {
+    rows.sort_by_key(|row| std::cmp::Reverse(time(row)));
+    rows
+}
+
+/// Estimated against actual effort for one category.
+#[derive(Debug, Clone, Default)]
+pub struct EstimateRow {
+    pub category: String,
+    /// Completed tasks with a time estimate, and their totals.
+    pub timed_tasks: usize,
+    pub estimated: Duration,
+    pub actual: Duration,
+    /// Completed tasks estimated in story points, and their totals.
+    pub pointed_tasks: usize,
+    pub points: u64,
+    pub actual_for_points: Duration,
+}
+
+impl EstimateRow {
+    /// Actual time divided by estimated time; above 1.0 means underestimated.
+    pub fn ratio(&self) -> Option<f64> {
+        let estimated = self.estimated.num_seconds();
+        (estimated > 0).then(|| self.actual.num_seconds() as f64 / estimated as f64)
+    }
+
+    /// Average actual time spent per story point.
+    pub fn time_per_point(&self) -> Option<Duration> {
+        let points = i64::try_from(self.points)
+            .ok()
+            .filter(|points| *points > 0)?;
+        Some(Duration::milliseconds(
+            self.actual_for_points.num_milliseconds() / points,
+        ))
+    }
+
+    fn add(&mut self, estimate: Estimate, actual: Duration) {
+        match estimate {
+            Estimate::Minutes(minutes) => {
+                self.timed_tasks += 1;
+                self.estimated += Duration::minutes(minutes as i64);
+                self.actual += actual;
+            }
+            Estimate::Points(points) => {
+                self.pointed_tasks += 1;
+                self.points += u64::from(points);
+                self.actual_for_points += actual;
+            }
+        }
+    }
+}
+
+/// Estimate accuracy per category over completed, estimated tasks. Actual
+/// time is tracked time when there is any, otherwise creation to completion.
+#[derive(Debug, Clone)]
+pub struct EstimateReport {
+    pub rows: Vec<EstimateRow>,
+    pub total: EstimateRow,
+}
+
+impl EstimateReport {
+    pub fn new(tasks: &[&Task]) -> Self {
+        let mut rows: BTreeMap<String, EstimateRow> = BTreeMap::new();
+        let mut total = EstimateRow {
+            category: "Total".to_string(),
+            ..EstimateRow::default()
+        };
+
+        for task in tasks {
+            if task.status != Status::Completed {
+                continue;
+            }
+            let (Some(estimate), Some(actual)) = (task.estimate, task.actual_time()) else {
+                continue;
+            };
+            rows.entry(task.category.clone())
+                .or_insert_with(|| EstimateRow {
+                    category: task.category.clone(),
+                    ..EstimateRow::default()
+                })
+                .add(estimate, actual);
+            total.add(estimate, actual);
+        }
+
+        Self {
+            rows: rows.into_values().collect(),
+            total,
+        }
+    }
+}
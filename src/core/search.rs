@@ -0,0 +1,271 @@
+use crate::core::task::Task;
+use crate::error::{Result, TodoError};
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// Text search over a task's name, tags, category and notes.
+///
+/// In fuzzy mode every term has to match some field, either as a
+/// case-insensitive substring, for terms of four or more characters as a
+/// word one typo away (`reprot` finds "report"), or for terms of three or
+/// more as a subsequence that isn't spread too thin (`rprt` finds it too).
+/// Matches in the name count most, then tags and category, then notes.
+#[derive(Debug, Clone)]
+pub enum Search {
+    Fuzzy(Vec<String>),
+    Regex(Regex),
+}
+
+/// Which part of a task a search matched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Tag,
+    Category,
+    Notes,
+}
+
+impl Field {
+    fn weight(self) -> u32 {
+        match self {
+            Field::Name => 3,
+            Field::Tag | Field::Category => 2,
+            Field::Notes => 1,
+        }
+    }
+}
+
+impl Search {
+    pub fn fuzzy(terms: &[String]) -> Self {
+        Search::Fuzzy(
+            terms
+                .iter()
+                .flat_map(|term| term.split_whitespace())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    /// A case-insensitive regular expression.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(Search::Regex)
+            .map_err(|err| TodoError::InvalidRegex(err.to_string()))
+    }
+
+    /// How well `task` matches, or `None` if it doesn't.
+    pub fn score(&self, task: &Task) -> Option<u32> {
+        let fields = fields(task);
+        match self {
+            Search::Fuzzy(terms) => terms.iter().try_fold(0, |total, term| {
+                fields
+                    .iter()
+                    .filter_map(|(field, text)| {
+                        fuzzy_match(text, term).map(|(score, _)| score * field.weight())
+                    })
+                    .max()
+                    .map(|best| total + best)
+            }),
+            Search::Regex(regex) => {
+                let score: u32 = fields
+                    .iter()
+                    .map(|(field, text)| regex.find_iter(text).count() as u32 * field.weight())
+                    .sum();
+                (score > 0).then_some(score)
+            }
+        }
+    }
+
+    /// Byte ranges of `text` the search matched, sorted and merged, for
+    /// highlighting.
+    pub fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = match self {
+            Search::Fuzzy(terms) => terms
+                .iter()
+                .filter_map(|term| fuzzy_match(text, term))
+                .flat_map(|(_, ranges)| ranges)
+                .collect(),
+            Search::Regex(regex) => regex
+                .find_iter(text)
+                .map(|found| found.range())
+                .filter(|range| !range.is_empty())
+                .collect(),
+        };
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
+fn fields(task: &Task) -> Vec<(Field, &str)> {
+    let mut fields = vec![(Field::Name, task.name.as_str())];
+    fields.extend(task.tags.iter().map(|tag| (Field::Tag, tag.as_str())));
+    fields.push((Field::Category, task.category.as_str()));
+    if !task.notes.is_empty() {
+        fields.push((Field::Notes, task.notes.as_str()));
+    }
+    fields
+}
+
+/// Tasks matching `search`, best match first; ties keep ID order.
+pub fn search<'a>(tasks: &[&'a Task], search: &Search) -> Vec<&'a Task> {
+    let mut hits: Vec<(u32, &Task)> = tasks
+        .iter()
+        .filter_map(|task| search.score(task).map(|score| (score, *task)))
+        .collect();
+    hits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.id.cmp(&b.1.id)));
+    hits.into_iter().map(|(_, task)| task).collect()
+}
+
+fn same_char(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Score and matched byte ranges of `term` in `text`. Substrings score
+/// 100, plus 20 at the start of a word; words with a typo 80; subsequences
+/// score less the more gaps they have.
+fn fuzzy_match(text: &str, term: &str) -> Option<(u32, Vec<Range<usize>>)> {
+    let term: Vec<char> = term.chars().collect();
+    if term.is_empty() {
+        return None;
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_end = |i: usize| chars.get(i).map_or(text.len(), |(at, _)| *at);
+
+    for start in 0..chars.len() {
+        let found = term.len() <= chars.len() - start
+            && term
+                .iter()
+                .zip(&chars[start..])
+                .all(|(t, (_, c))| same_char(*t, *c));
+        if found {
+            let word_start = start == 0 || !chars[start - 1].1.is_alphanumeric();
+            let score = 100 + if word_start { 20 } else { 0 };
+            let range = chars[start].0..byte_end(start + term.len());
+            return Some((score, vec![range]));
+        }
+    }
+
+    // A word one letter added, dropped, changed or swapped with its
+    // neighbour away from the term.
+    if term.len() >= 4 {
+        let typo = words(&chars).into_iter().find(|word| {
+            let word: Vec<char> = chars[word.clone()].iter().map(|(_, c)| *c).collect();
+            word.len().abs_diff(term.len()) <= 1 && edit_distance(&term, &word) <= 1
+        });
+        if let Some(word) = typo {
+            let range = chars[word.start].0..byte_end(word.end);
+            return Some((80, vec![range]));
+        }
+    }
+
+    if term.len() < 3 {
+        return None;
+    }
+    // The most compact greedy match over every possible starting point.
+    let matched = (0..chars.len())
+        .filter(|&start| same_char(term[0], chars[start].1))
+        .filter_map(|start| {
+            let mut matched = vec![start];
+            for (i, (_, c)) in chars.iter().enumerate().skip(start + 1) {
+                if matched.len() == term.len() {
+                    break;
+                }
+                if same_char(term[matched.len()], *c) {
+                    matched.push(i);
+                }
+            }
+            (matched.len() == term.len()).then_some(matched)
+        })
+        .min_by_key(|matched| matched[matched.len() - 1] - matched[0])?;
+    let gaps = (matched[matched.len() - 1] - matched[0] + 1 - term.len()) as u32;
+    if gaps > 2 * term.len() as u32 {
+        return None;
+    }
+    let ranges = matched
+        .iter()
+        .map(|&i| chars[i].0..byte_end(i + 1))
+        .collect();
+    Some((60u32.saturating_sub(gaps * 5).max(1), ranges))
+}
+
+/// Character index ranges of the alphanumeric runs in `chars`.
+fn words(chars: &[(usize, char)]) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, (_, c)) in chars.iter().enumerate() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                words.push(from..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        words.push(from..chars.len());
+    }
+    words
+}
+
+/// Insertions, deletions, substitutions and swaps of neighbouring
+/// characters needed to turn `a` into `b`, ignoring case.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(!same_char(a[i - 1], b[j - 1]));
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && same_char(a[i - 1], b[j - 2]) && same_char(a[i - 2], b[j - 1]) {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finds(term: &str, text: &str) -> bool {
+        fuzzy_match(text, term).is_some()
+    }
+
+    #[test]
+    fn fuzzy_terms_allow_one_typo_per_word() {
+        let text = "write quarterly report";
+        assert!(finds("quarterly", text));
+        assert!(finds("reprot", text));
+        assert!(finds("quartelry", text));
+        assert!(finds("reportt", text));
+        assert!(finds("rport", text));
+        assert!(finds("rprt", text));
+        assert!(finds("rpeort", text));
+        assert!(!finds("rpoert", text));
+        assert!(!finds("writes", "white"));
+    }
+
+    #[test]
+    fn typos_highlight_the_whole_word() {
+        let search = Search::fuzzy(&["reprot".to_string()]);
+        assert_eq!(search.highlights("write quarterly report"), vec![16..22]);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::core::task::{Estimate, Status, Task};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A task field tasks can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Id,
+    Name,
+    Status,
+    Priority,
+    Category,
+    Tags,
+    Due,
+    Scheduled,
+    Wait,
+    Created,
+    Updated,
+    Started,
+    Completed,
+    Estimate,
+    Parent,
+    Urgency,
+}
+
+const FIELDS: &[(&str, SortField)] = &[
+    ("id", SortField::Id),
+    ("name", SortField::Name),
+    ("status", SortField::Status),
+    ("priority", SortField::Priority),
+    ("category", SortField::Category),
+    ("tags", SortField::Tags),
+    ("due", SortField::Due),
+    ("scheduled", SortField::Scheduled),
+    ("wait", SortField::Wait),
+    ("created", SortField::Created),
+    ("updated", SortField::Updated),
+    ("started", SortField::Started),
+    ("completed", SortField::Completed),
+    ("estimate", SortField::Estimate),
+    ("parent", SortField::Parent),
+    ("urgency", SortField::Urgency),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+/// An ordering over tasks, written as comma-separated fields with `-` for
+/// descending, e.g. `due,-priority,category`.
+///
+/// Tasks missing a value (no due date, say) come after those that have one
+/// in either direction, and ties are broken by ID so the order is stable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortOrder(pub Vec<SortKey>);
+
+impl Default for SortOrder {
+    /// Work that is under way or still to do first, most urgent first.
+    fn default() -> Self {
+        "status,-urgency".parse().unwrap()
+    }
+}
+
+impl SortOrder {
+    /// Compare two tasks; `urgency` scores them for the `urgency` field.
+    pub fn compare(&self, a: &Task, b: &Task, urgency: &dyn Fn(&Task) -> f64) -> Ordering {
+        self.0
+            .iter()
+            .map(|key| key.compare(a, b, urgency))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.id.cmp(&b.id))
+    }
+
+    /// Sort `tasks`, scoring each once with `urgency` if the order uses it.
+    pub fn sort(&self, tasks: &mut [&Task], urgency: impl Fn(&Task) -> f64) {
+        let scores: HashMap<u64, f64> = if self.0.iter().any(|key| key.field == SortField::Urgency)
+        {
+            tasks.iter().map(|task| (task.id, urgency(task))).collect()
+        } else {
+            HashMap::new()
+        };
+        let lookup = |task: &Task| scores.get(&task.id).copied().unwrap_or(0.0);
+        tasks.sort_by(|a, b| self.compare(a, b, &lookup));
+    }
+}
+
+/// Position of a status in a work queue: started work first, closed last.
+pub(crate) fn status_rank(status: &Status) -> u8 {
+    match status {
+        Status::InProgress => 0,
+        Status::Pending => 1,
+        Status::Waiting => 2,
+        Status::Blocked => 3,
+        Status::Completed => 4,
+        Status::Cancelled => 5,
+    }
+}
+
+/// Time estimates before story points; the two don't compare.
+fn estimate_rank(estimate: &Estimate) -> (u8, u32) {
+    match estimate {
+        Estimate::Minutes(minutes) => (0, *minutes),
+        Estimate::Points(points) => (1, *points),
+    }
+}
+
+impl SortKey {
+    fn compare(&self, a: &Task, b: &Task, urgency: &dyn Fn(&Task) -> f64) -> Ordering {
+        fn text(value: &str) -> String {
+            value.to_lowercase()
+        }
+        fn date(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> Option<Ordering> {
+            a.zip(b).map(|(a, b)| a.cmp(&b))
+        }
+
+        let ordering = match self.field {
+            SortField::Id => Some(a.id.cmp(&b.id)),
+            SortField::Name => Some(text(&a.name).cmp(&text(&b.name))),
+            SortField::Status => Some(status_rank(&a.status).cmp(&status_rank(&b.status))),
+            SortField::Priority => Some(a.priority.cmp(&b.priority)),
+            SortField::Category => Some(text(&a.category).cmp(&text(&b.category))),
+            SortField::Tags => match (a.tags.is_empty(), b.tags.is_empty()) {
+                (false, false) => Some(text(&a.tags.join(",")).cmp(&text(&b.tags.join(",")))),
+                _ => None,
+            },
+            SortField::Due => date(a.due_date, b.due_date),
+            SortField::Scheduled => date(a.scheduled, b.scheduled),
+            SortField::Wait => date(a.wait_until, b.wait_until),
+            SortField::Created => Some(a.create_at.cmp(&b.create_at)),
+            SortField::Updated => Some(a.update_at.cmp(&b.update_at)),
+            SortField::Started => date(a.started_at, b.started_at),
+            SortField::Completed => date(a.completed_at, b.completed_at),
+            SortField::Estimate => a
+                .estimate
+                .as_ref()
+                .zip(b.estimate.as_ref())
+                .map(|(a, b)| estimate_rank(a).cmp(&estimate_rank(b))),
+            SortField::Parent => a.parent_id.zip(b.parent_id).map(|(a, b)| a.cmp(&b)),
+            SortField::Urgency => Some(urgency(a).total_cmp(&urgency(b))),
+        };
+        match ordering {
+            Some(ordering) if self.descending => ordering.reverse(),
+            Some(ordering) => ordering,
+            // Only one (or neither) has the value: missing values go last.
+            None => self.has_value(b).cmp(&self.has_value(a)),
+        }
+    }
+
+    fn has_value(&self, task: &Task) -> bool {
+        match self.field {
+            SortField::Tags => !task.tags.is_empty(),
+            SortField::Due => task.due_date.is_some(),
+            SortField::Scheduled => task.scheduled.is_some(),
+            SortField::Wait => task.wait_until.is_some(),
+            SortField::Started => task.started_at.is_some(),
+            SortField::Completed => task.completed_at.is_some(),
+            SortField::Estimate => task.estimate.is_some(),
+            SortField::Parent => task.parent_id.is_some(),
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let (descending, name) = match key.strip_prefix('-') {
+                    Some(name) => (true, name),
+                    None => (false, key.strip_prefix('+').unwrap_or(key)),
+                };
+                FIELDS
+                    .iter()
+                    .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                    .map(|(_, field)| SortKey {
+                        field: *field,
+                        descending,
+                    })
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                        format!(
+                            "Unknown sort field '{}' (expected one of {})",
+                            name,
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err("Sort order is empty".to_string());
+        }
+        Ok(SortOrder(keys))
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self
+            .0
+            .iter()
+            .map(|key| {
+                let name = FIELDS
+                    .iter()
+                    .find(|(_, field)| *field == key.field)
+                    .map_or("?", |(name, _)| *name);
+                format!("{}{}", if key.descending { "-" } else { "" }, name)
+            })
+            .collect();
+        write!(f, "{}", keys.join(","))
+    }
+}
@@ -0,0 +1,179 @@
+use crate::core::storage::{self, Storage};
+use crate::core::store::{self, StoreData};
+use crate::core::task::Task;
+use crate::error::Result;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const DATA_FILE: &str = "tasks.json";
+
+/// Stores all tasks as a single pretty-printed JSON file.
+///
+/// The first access takes an exclusive advisory lock on `<file>.lock` and
+/// keeps it until the storage is dropped, so a whole load–modify–save cycle
+/// can't interleave with another process. Writes go to a temporary file that
+/// is renamed over the original, so a crash never leaves a truncated file.
+///
+/// Files written by older versions are migrated on load; the original is
+/// first copied to `<file>.v<version>.bak`.
+///
+/// `commit` writes the manager's copy of the tasks without reading the
+/// file again. `upsert` and `delete` are a full read and rewrite each.
+#[derive(Debug)]
+pub struct JsonFileStorage {
+    path: PathBuf,
+    lock: Option<File>,
+}
+
+impl JsonFileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: None,
+        }
+    }
+
+    /// `tasks.json` under the platform data directory.
+    pub fn default_path() -> Result<PathBuf> {
+        let mut path = dirs::data_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Data directory not found")
+        })?;
+        path.push("todolist");
+        path.push(DATA_FILE);
+        Ok(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(extension);
+        self.path.with_file_name(name)
+    }
+
+    fn lock(&mut self) -> Result<()> {
+        if self.lock.is_none() {
+            self.lock = Some(storage::lock(&self.path)?);
+        }
+        Ok(())
+    }
+
+    fn write_atomic(&self, data: &str) -> Result<()> {
+        let tmp_path = self.sibling("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp_path, &self.path)?;
+
+        // Persist the rename itself; not every platform can open a directory.
+        #[cfg(unix)]
+        if let Some(parent) = self.path.parent() {
+            // A bare file name has an empty parent: the working directory.
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for JsonFileStorage {
+    fn load(&mut self) -> Result<StoreData> {
+        self.lock()?;
+        if !self.path.exists() {
+            return Ok(StoreData::default());
+        }
+        let raw = fs::read_to_string(&self.path)?;
+        let (data, migrated_from) = store::migrate(serde_json::from_str(&raw)?)?;
+        if let Some(version) = migrated_from {
+            fs::copy(&self.path, self.sibling(&format!("v{version}.bak")))?;
+            self.save(&data)?;
+        }
+        Ok(data)
+    }
+
+    fn save(&mut self, data: &StoreData) -> Result<()> {
+        self.lock()?;
+        let data = serde_json::to_string_pretty(data)?;
+        self.write_atomic(&data)
+    }
+
+    fn upsert(&mut self, task: &Task) -> Result<()> {
+        let mut data = self.load()?;
+        data.upsert(task.clone());
+        self.save(&data)
+    }
+
+    fn delete(&mut self, id: u64) -> Result<()> {
+        let mut data = self.load()?;
+        if data.tasks.remove(&id).is_some() {
+            self.save(&data)?;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.lock = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TodoError;
+    use uuid::Uuid;
+
+    /// A fresh directory under the system temp dir, removed by the caller.
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("todo-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migrating_keeps_a_backup() {
+        let dir = temp_dir();
+        let path = dir.join(DATA_FILE);
+        let mut task = serde_json::to_value(Task::new(4, "old".to_string())).unwrap();
+        task.as_object_mut().unwrap().remove("uuid");
+        let old = serde_json::json!({ "4": task }).to_string();
+        fs::write(&path, &old).unwrap();
+
+        let data = JsonFileStorage::new(&path).load().unwrap();
+        assert_eq!(data.next_id, 5);
+        assert_eq!(
+            fs::read_to_string(dir.join("tasks.json.v0.bak")).unwrap(),
+            old
+        );
+        let saved: StoreData = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.version, store::SCHEMA_VERSION);
+        assert_eq!(saved.tasks[&4].uuid, data.tasks[&4].uuid);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_replace_the_file_and_hold_the_lock() {
+        let dir = temp_dir();
+        let path = dir.join(DATA_FILE);
+        let mut storage = JsonFileStorage::new(&path);
+        storage.upsert(&Task::new(1, "a".to_string())).unwrap();
+        assert!(!storage.sibling("tmp").exists());
+
+        let err = JsonFileStorage::new(&path).load().unwrap_err();
+        assert!(matches!(err, TodoError::Locked(_)), "{err}");
+        storage.release();
+        let data = JsonFileStorage::new(&path).load().unwrap();
+        assert_eq!(data.tasks[&1].name, "a");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use crate::core::storage::Storage;
+use crate::core::store::StoreData;
+use crate::core::task::Task;
+use crate::error::Result;
+
+/// Keeps tasks in memory only. Useful for tests and for embedding
+/// `TodoManager` without touching the user's data directory.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage {
+    data: StoreData,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tasks(tasks: impl IntoIterator<Item = Task>) -> Self {
+        let mut data = StoreData::default();
+        for task in tasks {
+            data.upsert(task);
+        }
+        Self { data }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> Result<StoreData> {
+        Ok(self.data.clone())
+    }
+
+    fn save(&mut self, data: &StoreData) -> Result<()> {
+        self.data = data.clone();
+        Ok(())
+    }
+
+    fn upsert(&mut self, task: &Task) -> Result<()> {
+        self.data.upsert(task.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64) -> Result<()> {
+        self.data.tasks.remove(&id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_what_was_written() {
+        let mut storage = MemoryStorage::with_tasks([Task::new(1, "a".to_string())]);
+        storage.upsert(&Task::new(2, "b".to_string())).unwrap();
+        storage.delete(1).unwrap();
+        let data = storage.load().unwrap();
+        assert_eq!(data.tasks.keys().collect::<Vec<_>>(), [&2]);
+        assert_eq!(data.next_id, 3);
+
+        let mut copy = MemoryStorage::new();
+        copy.save(&data).unwrap();
+        assert_eq!(copy.load().unwrap().tasks, data.tasks);
+    }
+}
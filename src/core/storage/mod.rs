@@ -0,0 +1,133 @@
+use crate::core::journal::Change;
+use crate::core::store::StoreData;
+use crate::core::task::{Priority, Status, Task};
+use crate::error::{Result, TodoError};
+use chrono::{DateTime, Utc};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod json;
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use json::JsonFileStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+#[cfg(not(test))]
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(test)]
+const LOCK_TIMEOUT: Duration = Duration::from_millis(200);
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+/// Take an exclusive advisory lock on `<data>.lock`, waiting a few seconds
+/// for another process to let go of it. The lock is held until the
+/// returned file is dropped.
+fn lock(data: &Path) -> Result<File> {
+    if let Some(parent) = data.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut name = data.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    let lock_path = data.with_file_name(name);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                thread::sleep(LOCK_RETRY);
+            }
+            Err(TryLockError::WouldBlock) => return Err(TodoError::Locked(lock_path)),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// Filters that a backend may be able to answer without scanning every task.
+/// Every field that is set must match.
+#[derive(Debug, Default, Clone)]
+pub struct TaskQuery {
+    pub status: Option<Status>,
+    pub priority: Option<Priority>,
+    pub category: Option<String>,
+    /// Due on or after this instant.
+    pub due_after: Option<DateTime<Utc>>,
+    /// Due strictly before this instant.
+    pub due_before: Option<DateTime<Utc>>,
+}
+
+impl TaskQuery {
+    pub fn matches(&self, task: &Task) -> bool {
+        if let Some(status) = &self.status
+            && task.status != *status
+        {
+            return false;
+        }
+        if let Some(priority) = &self.priority
+            && task.priority != *priority
+        {
+            return false;
+        }
+        if let Some(category) = &self.category
+            && task.category != *category
+        {
+            return false;
+        }
+        if self.due_after.is_some() || self.due_before.is_some() {
+            let Some(due) = task.due_date else {
+                return false;
+            };
+            if self.due_after.is_some_and(|after| due < after)
+                || self.due_before.is_some_and(|before| due >= before)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A place where `TodoManager` keeps its tasks between runs.
+pub trait Storage {
+    /// Load everything that is stored, migrated to the current schema version.
+    fn load(&mut self) -> Result<StoreData>;
+
+    /// Replace everything that is stored with `data`.
+    fn save(&mut self, data: &StoreData) -> Result<()>;
+
+    /// Store the outcome of one `TodoManager` operation. `data` is every
+    /// task after it, and `changes` are the tasks it added, changed or
+    /// removed. Either all of the changes are stored or none are.
+    ///
+    /// By default everything is saved at once.
+    fn commit(&mut self, data: &StoreData, _changes: &[Change]) -> Result<()> {
+        self.save(data)
+    }
+
+    /// Insert `task`, or overwrite the stored task with the same id.
+    fn upsert(&mut self, task: &Task) -> Result<()>;
+
+    /// Remove the task with `id`. Removing a missing task is not an error.
+    fn delete(&mut self, id: u64) -> Result<()>;
+
+    /// The tasks matching `query`, together with their subtasks, the tasks
+    /// they depend on and the tasks depending on them, for backends that
+    /// can pick those out without reading everything. `None` tells the
+    /// caller to `load` everything instead.
+    fn query(&mut self, _query: &TaskQuery) -> Result<Option<StoreData>> {
+        Ok(None)
+    }
+
+    /// Give up any lock held on the stored data until the next `load`.
+    fn release(&mut self) {}
+}
@@ -0,0 +1,459 @@
+use crate::core::journal::Change;
+use crate::core::storage::{self, Storage, TaskQuery};
+use crate::core::store::{self, SCHEMA_VERSION, StoreData};
+use crate::core::task::Task;
+use crate::error::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{Connection, OptionalExtension, Transaction, params, params_from_iter};
+use serde_json::{Map, Value, json};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+const DATA_FILE: &str = "tasks.db";
+
+// The complete task is kept as JSON in `tasks.data`, so adding a field to
+// `Task` needs no schema change. The remaining columns and the other tables
+// are projections that exist to make `query` cheap.
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS categories (
+        id   INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE IF NOT EXISTS tags (
+        id   INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE IF NOT EXISTS tasks (
+        id           INTEGER PRIMARY KEY,
+        name         TEXT NOT NULL,
+        status       TEXT NOT NULL,
+        priority     TEXT NOT NULL,
+        category_id  INTEGER NOT NULL REFERENCES categories(id),
+        create_at    TEXT NOT NULL,
+        update_at    TEXT NOT NULL,
+        completed_at TEXT,
+        due_date     TEXT,
+        parent_id    INTEGER,
+        data         TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS task_tags (
+        task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+        tag_id  INTEGER NOT NULL REFERENCES tags(id),
+        PRIMARY KEY (task_id, tag_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS task_deps (
+        task_id    INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+        depends_on INTEGER NOT NULL,
+        PRIMARY KEY (task_id, depends_on)
+    );
+";
+
+const INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS idx_tasks_status ON tasks(status);
+    CREATE INDEX IF NOT EXISTS idx_tasks_priority ON tasks(priority);
+    CREATE INDEX IF NOT EXISTS idx_tasks_category ON tasks(category_id);
+    CREATE INDEX IF NOT EXISTS idx_tasks_due_date ON tasks(due_date);
+    CREATE INDEX IF NOT EXISTS idx_task_tags_tag ON task_tags(tag_id);
+    CREATE INDEX IF NOT EXISTS idx_tasks_parent ON tasks(parent_id);
+    CREATE INDEX IF NOT EXISTS idx_task_deps_depends_on ON task_deps(depends_on);
+";
+
+// Databases created before subtasks and dependencies had their own columns
+// get them filled in from the stored tasks.
+const ADD_LINKS: &str = "
+    ALTER TABLE tasks ADD COLUMN parent_id INTEGER;
+    UPDATE tasks SET parent_id = json_extract(data, '$.parent_id');
+    INSERT OR IGNORE INTO task_deps (task_id, depends_on)
+        SELECT tasks.id, deps.value FROM tasks, json_each(tasks.data, '$.depends_on') AS deps;
+";
+
+/// Stores tasks in a SQLite database with indexed status, priority,
+/// category, due date, parent and dependency columns. Each `commit` writes
+/// only the changed rows, and `query` reads only the tasks a `list` needs.
+///
+/// Like `JsonFileStorage`, loading takes an exclusive lock on `<file>.lock`
+/// that is kept until the storage is dropped, so two processes can't both
+/// read the same tasks and then overwrite each other's changes. `query`
+/// only reads and takes no lock.
+///
+/// The schema version lives in `PRAGMA user_version`. Databases written by
+/// older versions are migrated on load; the original is first copied to
+/// `<file>.v<version>.bak`.
+pub struct SqliteStorage {
+    path: PathBuf,
+    conn: Connection,
+    lock: Option<File>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(&path)?;
+        conn.execute_batch(SCHEMA)?;
+        let linked: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('tasks') WHERE name = 'parent_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !linked {
+            conn.execute_batch(ADD_LINKS)?;
+        }
+        conn.execute_batch(INDEXES)?;
+
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        let empty: bool = conn.query_row("SELECT NOT EXISTS (SELECT 1 FROM tasks)", [], |row| {
+            row.get(0)
+        })?;
+        if version == 0 && empty {
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        Ok(Self {
+            path,
+            conn,
+            lock: None,
+        })
+    }
+
+    /// `tasks.db` under the platform data directory.
+    pub fn default_path() -> Result<PathBuf> {
+        let mut path = dirs::data_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Data directory not found")
+        })?;
+        path.push("todolist");
+        path.push(DATA_FILE);
+        Ok(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock(&mut self) -> Result<()> {
+        if self.lock.is_none() {
+            self.lock = Some(storage::lock(&self.path)?);
+        }
+        Ok(())
+    }
+
+    fn stored_next_id(&self) -> Result<u64> {
+        let next_id = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .optional()?;
+        Ok(next_id.unwrap_or(1) as u64)
+    }
+}
+
+// Fixed-width UTC timestamps compare correctly as plain text.
+fn timestamp(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn name_id(tx: &Transaction, table: &str, name: &str) -> Result<i64> {
+    tx.execute(
+        &format!("INSERT OR IGNORE INTO {table} (name) VALUES (?1)"),
+        params![name],
+    )?;
+    let id = tx.query_row(
+        &format!("SELECT id FROM {table} WHERE name = ?1"),
+        params![name],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+// Only ever raises the stored counter, so ids of removed tasks stay retired.
+fn bump_next_id(tx: &Transaction, next_id: u64) -> Result<()> {
+    tx.execute(
+        "INSERT INTO meta (key, value) VALUES ('next_id', ?1)
+         ON CONFLICT(key) DO UPDATE SET value = max(value, excluded.value)",
+        params![next_id as i64],
+    )?;
+    Ok(())
+}
+
+fn write_task(tx: &Transaction, task: &Task) -> Result<()> {
+    let category_id = name_id(tx, "categories", &task.category)?;
+    tx.execute(
+        "INSERT INTO tasks (id, name, status, priority, category_id, create_at,
+                            update_at, completed_at, due_date, parent_id, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+         ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name,
+            status = excluded.status,
+            priority = excluded.priority,
+            category_id = excluded.category_id,
+            create_at = excluded.create_at,
+            update_at = excluded.update_at,
+            completed_at = excluded.completed_at,
+            due_date = excluded.due_date,
+            parent_id = excluded.parent_id,
+            data = excluded.data",
+        params![
+            task.id as i64,
+            task.name,
+            task.status.to_string(),
+            task.priority.to_string(),
+            category_id,
+            timestamp(task.create_at),
+            timestamp(task.update_at),
+            task.completed_at.map(timestamp),
+            task.due_date.map(timestamp),
+            task.parent_id.map(|id| id as i64),
+            serde_json::to_string(task)?,
+        ],
+    )?;
+
+    tx.execute(
+        "DELETE FROM task_tags WHERE task_id = ?1",
+        params![task.id as i64],
+    )?;
+    for tag in &task.tags {
+        let tag_id = name_id(tx, "tags", tag)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id) VALUES (?1, ?2)",
+            params![task.id as i64, tag_id],
+        )?;
+    }
+
+    tx.execute(
+        "DELETE FROM task_deps WHERE task_id = ?1",
+        params![task.id as i64],
+    )?;
+    for dep in &task.depends_on {
+        tx.execute(
+            "INSERT OR IGNORE INTO task_deps (task_id, depends_on) VALUES (?1, ?2)",
+            params![task.id as i64, *dep as i64],
+        )?;
+    }
+    Ok(())
+}
+
+fn delete_task(tx: &Transaction, id: u64) -> Result<()> {
+    tx.execute("DELETE FROM tasks WHERE id = ?1", params![id as i64])?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<StoreData> {
+        self.lock()?;
+        // The SQLite backend was introduced with version 1 of the task layout.
+        let version: u32 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        let version = version.max(1);
+
+        let mut tasks = Map::new();
+        let mut next_id = self.stored_next_id()?;
+        {
+            let mut stmt = self.conn.prepare("SELECT id, data FROM tasks")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (id, data) = row?;
+                next_id = next_id.max(id + 1);
+                tasks.insert(id.to_string(), serde_json::from_str(&data)?);
+            }
+        }
+
+        let raw = json!({
+            "version": version,
+            "next_id": next_id,
+            "tasks": Value::Object(tasks),
+        });
+        let (data, migrated_from) = store::migrate(raw)?;
+        if let Some(version) = migrated_from {
+            let mut backup = self.path.clone().into_os_string();
+            backup.push(format!(".v{version}.bak"));
+            std::fs::copy(&self.path, backup)?;
+            self.save(&data)?;
+        }
+        Ok(data)
+    }
+
+    fn save(&mut self, data: &StoreData) -> Result<()> {
+        self.lock()?;
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM task_deps", [])?;
+        tx.execute("DELETE FROM task_tags", [])?;
+        tx.execute("DELETE FROM tasks", [])?;
+        for task in data.tasks.values() {
+            write_task(&tx, task)?;
+        }
+        bump_next_id(&tx, data.next_id)?;
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn commit(&mut self, data: &StoreData, changes: &[Change]) -> Result<()> {
+        self.lock()?;
+        let tx = self.conn.transaction()?;
+        for change in changes {
+            match &change.after {
+                Some(task) => write_task(&tx, task)?,
+                None => delete_task(&tx, change.id)?,
+            }
+        }
+        bump_next_id(&tx, data.next_id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn upsert(&mut self, task: &Task) -> Result<()> {
+        self.lock()?;
+        let tx = self.conn.transaction()?;
+        write_task(&tx, task)?;
+        bump_next_id(&tx, task.id + 1)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64) -> Result<()> {
+        self.lock()?;
+        let tx = self.conn.transaction()?;
+        delete_task(&tx, id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn query(&mut self, query: &TaskQuery) -> Result<Option<StoreData>> {
+        // Rows of an older layout have to go through `load` to be migrated.
+        let version: u32 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            return Ok(None);
+        }
+
+        let mut clauses = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+
+        if let Some(status) = &query.status {
+            values.push(status.to_string());
+            clauses.push(format!("status = ?{}", values.len()));
+        }
+        if let Some(priority) = &query.priority {
+            values.push(priority.to_string());
+            clauses.push(format!("priority = ?{}", values.len()));
+        }
+        if let Some(category) = &query.category {
+            values.push(category.clone());
+            clauses.push(format!(
+                "category_id = (SELECT id FROM categories WHERE name = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(after) = query.due_after {
+            values.push(timestamp(after));
+            clauses.push(format!("due_date >= ?{}", values.len()));
+        }
+        if let Some(before) = query.due_before {
+            values.push(timestamp(before));
+            clauses.push(format!("due_date < ?{}", values.len()));
+        }
+
+        let mut matched = "SELECT id FROM tasks".to_string();
+        if !clauses.is_empty() {
+            matched.push_str(" WHERE ");
+            matched.push_str(&clauses.join(" AND "));
+        }
+        // Along with the matches come their subtasks, dependencies and
+        // dependents, so progress, blockers and urgency can be worked out.
+        let sql = format!(
+            "WITH matched AS ({matched})
+             SELECT data FROM tasks
+             WHERE id IN (SELECT id FROM matched)
+                OR parent_id IN (SELECT id FROM matched)
+                OR id IN (SELECT depends_on FROM task_deps
+                          WHERE task_id IN (SELECT id FROM matched))
+                OR id IN (SELECT task_id FROM task_deps
+                          WHERE depends_on IN (SELECT id FROM matched))"
+        );
+
+        let mut data = StoreData {
+            next_id: self.stored_next_id()?,
+            ..StoreData::default()
+        };
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row| {
+            row.get::<_, String>(0)
+        })?;
+        for row in rows {
+            data.upsert(serde_json::from_str(&row?)?);
+        }
+        Ok(Some(data))
+    }
+
+    fn release(&mut self) {
+        self.lock = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::store::{TaskOptions, TodoManager};
+    use crate::error::TodoError;
+    use uuid::Uuid;
+
+    #[test]
+    fn query_loads_only_the_matches_and_their_links() {
+        let path = std::env::temp_dir().join(format!("todo-test-{}.db", Uuid::new_v4()));
+        let mut manager = TodoManager::with_storage(SqliteStorage::open(&path).unwrap()).unwrap();
+        let work = TaskOptions {
+            category: Some("work".to_string()),
+            ..TaskOptions::default()
+        };
+        let report = manager.add_task("report", work).unwrap();
+        let draft = TaskOptions {
+            parent: Some(report),
+            ..TaskOptions::default()
+        };
+        let draft = manager.add_task("draft", draft).unwrap();
+        let send = manager.add_task("send", TaskOptions::default()).unwrap();
+        manager.add_dependencies(send, &[report]).unwrap();
+        let other = manager.add_task("other", TaskOptions::default()).unwrap();
+
+        let query = TaskQuery {
+            category: Some("work".to_string()),
+            ..TaskQuery::default()
+        };
+        let mut manager =
+            TodoManager::with_query(SqliteStorage::open(&path).unwrap(), &query).unwrap();
+        let ids: Vec<u64> = manager
+            .query_tasks(&query)
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(ids, vec![report]);
+        assert_eq!(manager.subtask_progress(report), Some((0, 1)));
+        assert!(manager.is_blocking(report));
+        assert!(manager.get_task(draft).is_some());
+        assert!(manager.get_task(other).is_none());
+
+        let err = manager
+            .add_task("more", TaskOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, TodoError::PartiallyLoaded), "{err}");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,154 +1,854 @@
-use crate::core::task::{Priority, Task};
+use crate::core::dates;
+use crate::core::journal::{Change, EntryKind, Journal, JournalEntry};
+use crate::core::recurrence::Recurrence;
+use crate::core::storage::{JsonFileStorage, Storage, TaskQuery};
+use crate::core::task::{
+    Annotation, Estimate, Priority, Status, Task, TimeSession, UrgencyCoefficients,
+};
+use crate::core::time::{Clock, SystemClock, Zone};
 use crate::error::{Result, TodoError};
-use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
 
-const DATA_FILE: &str = "tasks.json";
+/// Upgrades stored data from version `n` to `n + 1`, where `n` is the
+/// migration's index in `MIGRATIONS`.
+type Migration = fn(Value) -> Result<Value>;
+
+const MIGRATIONS: &[Migration] = &[migrate_v0_envelope, migrate_v1_uuids];
+
+/// Version of the data layout written by this build.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Everything a storage backend persists, tagged with its schema version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreData {
+    pub version: u32,
+    pub next_id: u64,
+    pub tasks: HashMap<u64, Task>,
+}
+
+impl Default for StoreData {
+    fn default() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            next_id: 1,
+            tasks: HashMap::new(),
+        }
+    }
+}
+
+impl StoreData {
+    /// Add or replace `task`, making sure its id is never handed out again.
+    pub fn upsert(&mut self, task: Task) {
+        self.next_id = self.next_id.max(task.id + 1);
+        self.tasks.insert(task.id, task);
+    }
+}
+
+/// Upgrade raw stored data to `SCHEMA_VERSION`. Returns the upgraded data and,
+/// if anything had to change, the version it was upgraded from.
+///
+/// Version 0 is the bare `{id: task}` map written before versioning existed.
+pub fn migrate(mut value: Value) -> Result<(StoreData, Option<u32>)> {
+    let version = match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| TodoError::InvalidData("schema version is not a number".to_string()))?
+            as u32,
+        None => 0,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(TodoError::UnsupportedSchema(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        value = migration(value)?;
+    }
+    let data = serde_json::from_value(value)?;
+    let migrated_from = (version < SCHEMA_VERSION).then_some(version);
+    Ok((data, migrated_from))
+}
+
+fn migrate_v0_envelope(tasks: Value) -> Result<Value> {
+    let next_id = tasks
+        .as_object()
+        .ok_or_else(|| TodoError::InvalidData("expected a map of tasks".to_string()))?
+        .keys()
+        .filter_map(|id| id.parse::<u64>().ok())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    Ok(json!({
+        "version": 1,
+        "next_id": next_id,
+        "tasks": tasks,
+    }))
+}
+
+fn migrate_v1_uuids(mut data: Value) -> Result<Value> {
+    let tasks = data
+        .get_mut("tasks")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| TodoError::InvalidData("expected a map of tasks".to_string()))?;
+    for task in tasks.values_mut().filter_map(Value::as_object_mut) {
+        task.entry("uuid")
+            .or_insert_with(|| Value::String(Uuid::new_v4().to_string()));
+    }
+    data["version"] = json!(2);
+    Ok(data)
+}
+
+/// Optional fields for `TodoManager::add_task`. Dates and tags are given as
+/// they are typed on the command line.
+#[derive(Debug, Default, Clone)]
+pub struct TaskOptions {
+    pub priority: Option<Priority>,
+    pub category: Option<String>,
+    pub due: Option<String>,
+    /// Comma-separated tags.
+    pub tags: Option<String>,
+    pub parent: Option<u64>,
+    pub recurrence: Option<Recurrence>,
+    pub estimate: Option<Estimate>,
+    pub scheduled: Option<String>,
+    pub wait: Option<String>,
+}
+
+/// Changes for `TodoManager::edit_task`. `None` leaves a field as it is; an
+/// empty string clears the optional ones (due, scheduled, wait, estimate).
+#[derive(Debug, Default, Clone)]
+pub struct TaskEdits {
+    pub name: Option<String>,
+    pub priority: Option<Priority>,
+    pub category: Option<String>,
+    pub due: Option<String>,
+    pub scheduled: Option<String>,
+    pub wait: Option<String>,
+    pub estimate: Option<String>,
+}
+
+/// Links between tasks looked up from the other end, kept in step with the
+/// manager's tasks so they never need a scan over all of them.
+#[derive(Debug, Default)]
+struct Links {
+    /// Direct subtasks of each task.
+    children: HashMap<u64, BTreeSet<u64>>,
+    /// Tasks that depend on each task.
+    dependents: HashMap<u64, BTreeSet<u64>>,
+}
+
+impl Links {
+    fn new<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> Self {
+        let mut links = Self::default();
+        for task in tasks {
+            links.add(task);
+        }
+        links
+    }
+
+    fn add(&mut self, task: &Task) {
+        if let Some(parent) = task.parent_id {
+            self.children.entry(parent).or_default().insert(task.id);
+        }
+        for dep in &task.depends_on {
+            self.dependents.entry(*dep).or_default().insert(task.id);
+        }
+    }
+
+    fn remove(&mut self, task: &Task) {
+        if let Some(children) = task.parent_id.and_then(|p| self.children.get_mut(&p)) {
+            children.remove(&task.id);
+        }
+        for dep in &task.depends_on {
+            if let Some(dependents) = self.dependents.get_mut(dep) {
+                dependents.remove(&task.id);
+            }
+        }
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
 pub struct TodoManager {
-    tasks: HashMap<u64, Task>,
-    next_id: u64,
+    /// Every task, as stored. Authoritative while the storage lock is held.
+    data: StoreData,
+    /// Whether `data` holds only what a query selected (see `with_query`).
+    partial: bool,
+    links: Links,
+    storage: Box<dyn Storage>,
+    journal: Journal,
+    clock: Box<dyn Clock>,
+    zone: Zone,
+    urgency: UrgencyCoefficients,
 }
 
 impl TodoManager {
+    /// Open the manager on the default `tasks.json` and its journal in the
+    /// user's data directory.
     pub fn new() -> Result<Self> {
-        let mut manager = Self {
-            tasks: HashMap::new(),
-            next_id: 1,
-        };
+        let path = JsonFileStorage::default_path()?;
+        let journal = Journal::open(Journal::path_for(&path));
+        Ok(Self::with_storage(JsonFileStorage::new(path))?.with_journal(journal))
+    }
+
+    /// Open the manager on an arbitrary storage backend. Mutations are
+    /// journaled in memory only until `with_journal` supplies a journal.
+    pub fn with_storage(storage: impl Storage + 'static) -> Result<Self> {
+        let mut manager = Self::unloaded(storage);
         manager.load()?;
         Ok(manager)
     }
 
-    fn load(&mut self) -> Result<()> {
-        let path = self.data_path()?;
-        if path.exists() {
-            let data = fs::read_to_string(path)?;
-            let tasks: HashMap<u64, Task> = serde_json::from_str(&data)?;
-            self.tasks = tasks;
-            self.next_id = self.tasks.keys().max().unwrap_or(&0) + 1;
+    /// Open the manager holding only the tasks `query` selects, with their
+    /// subtasks, dependencies and dependents, if the backend can pick those
+    /// out by itself; otherwise everything is loaded as by `with_storage`.
+    ///
+    /// This is enough to show the selected tasks, but nothing else: a
+    /// manager opened this way refuses to change tasks.
+    pub fn with_query(storage: impl Storage + 'static, query: &TaskQuery) -> Result<Self> {
+        let mut manager = Self::unloaded(storage);
+        match manager.storage.query(query)? {
+            Some(data) => {
+                manager.links = Links::new(data.tasks.values());
+                manager.data = data;
+                manager.partial = true;
+            }
+            None => manager.load()?,
+        }
+        Ok(manager)
+    }
+
+    fn unloaded(storage: impl Storage + 'static) -> Self {
+        Self {
+            data: StoreData::default(),
+            partial: false,
+            links: Links::default(),
+            storage: Box::new(storage),
+            journal: Journal::in_memory(),
+            clock: Box::new(SystemClock),
+            zone: Zone::Local,
+            urgency: UrgencyCoefficients::default(),
         }
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    pub fn journal(&mut self) -> &mut Journal {
+        &mut self.journal
+    }
+
+    /// Evaluate dates against `clock` instead of the system time. This
+    /// moves what "today", "overdue" and relative dates mean; the
+    /// timestamps recorded on changes (created, completed, ...) stay real.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Enter and evaluate dates in `zone` instead of the system zone.
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    /// Weigh `urgency` with these coefficients instead of the defaults.
+    pub fn with_urgency(mut self, coefficients: UrgencyCoefficients) -> Self {
+        self.urgency = coefficients;
+        self
+    }
+
+    /// The current time according to the manager's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// The zone dates are entered and shown in.
+    pub fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    /// Run `f` without holding the storage lock, so other processes can
+    /// change tasks meanwhile (e.g. while an editor is open), then reload
+    /// the tasks and journal.
+    pub fn released<T>(&mut self, f: impl FnOnce() -> T) -> Result<T> {
+        self.storage.release();
+        let value = f();
+        self.load()?;
+        self.journal.reload();
+        Ok(value)
+    }
+
+    fn load(&mut self) -> Result<()> {
+        self.data = self.storage.load()?;
+        self.data.next_id = self
+            .data
+            .next_id
+            .max(self.data.tasks.keys().max().unwrap_or(&0) + 1);
+        self.partial = false;
+        self.links = Links::new(self.data.tasks.values());
         Ok(())
     }
 
-    fn data_path(&self) -> Result<PathBuf> {
-        let mut path = dirs::data_dir().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::NotFound, "Data directory not found")
-        })?;
-        path.push("todolist");
-        path.push(DATA_FILE);
-        Ok(path)
-    }
-
-    pub fn add_task(
-        &mut self,
-        name: &str,
-        priority: Option<Priority>,
-        category: Option<&str>,
-        due_date: Option<&str>,
-        tags: Option<&str>,
-    ) -> Result<u64> {
-        let mut task = Task::new(self.next_id, name.to_string());
-        if let Some(pri) = priority {
+    pub fn add_task(&mut self, name: &str, options: TaskOptions) -> Result<u64> {
+        let mut task = Task::new(self.data.next_id, name.to_string());
+        if let Some(parent_id) = options.parent {
+            if !self.data.tasks.contains_key(&parent_id) {
+                return Err(TodoError::TaskNotFound(parent_id));
+            }
+            task.parent_id = Some(parent_id);
+        }
+
+        if let Some(pri) = options.priority {
             task.set_priority(pri);
         }
 
-        if let Some(cat) = category {
-            task.set_category(cat.to_string())
+        if let Some(cat) = options.category {
+            task.set_category(cat)
         }
 
-        if let Some(due) = due_date {
-            if let Some(parsed_due) = Self::parse_due_date(due)? {
-                task.set_due_date(parsed_due);
-            }
+        if let Some(due) = options.due
+            && let Some(parsed_due) = dates::parse_due(&due, self.now(), &self.zone)?
+        {
+            task.set_due_date(parsed_due);
         }
 
-        if let Some(tags_str) = tags {
+        if let Some(tags_str) = options.tags {
             for tag in tags_str.split(',') {
                 task.add_tag(tag.trim().to_string())
             }
         }
 
-        let id = self.next_id;
-        self.tasks.insert(id, task);
-        self.next_id += 1;
-        self.save()?;
+        if let Some(scheduled) = options.scheduled {
+            task.scheduled = dates::parse_start(&scheduled, self.now(), &self.zone)?;
+        }
+
+        if let Some(wait) = options.wait {
+            task.wait_until = dates::parse_start(&wait, self.now(), &self.zone)?;
+        }
+
+        task.recurrence = options.recurrence;
+        task.estimate = options.estimate;
+
+        let id = self.data.next_id;
+        self.data.next_id += 1;
+        self.commit(
+            format!("add task {id}"),
+            vec![Change::new(id, None, Some(task))],
+        )?;
         Ok(id)
     }
 
-    pub fn edit_task(
-        &mut self,
-        id: u64,
-        name: Option<String>,
-        priority: Option<Priority>,
-        category: Option<String>,
-        due_date: Option<String>,
-    ) -> Result<()> {
-        if let Some(task) = self.tasks.get_mut(&id) {
-            if let Some(na) = name {
-                task.name = na;
-                task.update_at = Utc::now();
-            }
+    pub fn edit_task(&mut self, id: u64, edits: TaskEdits) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        if let Some(na) = edits.name {
+            task.name = na;
+            task.update_at = Utc::now();
+        }
 
-            if let Some(pri) = priority {
-                task.set_priority(pri);
-            }
+        if let Some(pri) = edits.priority {
+            task.set_priority(pri);
+        }
 
-            if let Some(cat) = category {
-                task.set_category(cat.to_string())
-            }
+        if let Some(cat) = edits.category {
+            task.set_category(cat)
+        }
 
-            if let Some(due) = due_date {
-                let parsed_due = if due.is_empty() {
-                    None
-                } else {
-                    Self::parse_due_date(&due)?
-                };
-                task.due_date = parsed_due;
-                task.update_at = Utc::now();
-            }
-            self.save()?;
-            Ok(())
-        } else {
-            Err(TodoError::TaskNotFound(id))
+        if let Some(due) = edits.due {
+            task.due_date = dates::parse_due(&due, self.now(), &self.zone)?;
+            task.update_at = Utc::now();
+        }
+
+        if let Some(scheduled) = edits.scheduled {
+            task.scheduled = dates::parse_start(&scheduled, self.now(), &self.zone)?;
+            task.update_at = Utc::now();
+        }
+
+        if let Some(wait) = edits.wait {
+            task.wait_until = dates::parse_start(&wait, self.now(), &self.zone)?;
+            task.update_at = Utc::now();
+        }
+
+        if let Some(estimate) = edits.estimate {
+            task.estimate = if estimate.is_empty() {
+                None
+            } else {
+                Some(estimate.parse().map_err(TodoError::InvalidEstimate)?)
+            };
+            task.update_at = Utc::now();
         }
+        self.commit(
+            format!("edit task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
     }
 
     pub fn list_tasks(&self) -> Vec<&Task> {
-        let mut tasks: Vec<&Task> = self.tasks.values().collect();
+        let mut tasks: Vec<&Task> = self.data.tasks.values().collect();
+        tasks.sort_by_key(|task| task.id);
+        tasks
+    }
+
+    /// Tasks matching `query`, ordered by id. A manager opened with
+    /// `with_query` only finds tasks its own query selected.
+    pub fn query_tasks(&self, query: &TaskQuery) -> Vec<&Task> {
+        let mut tasks: Vec<&Task> = self
+            .data
+            .tasks
+            .values()
+            .filter(|task| query.matches(task))
+            .collect();
         tasks.sort_by_key(|task| task.id);
         tasks
     }
 
     pub fn get_task(&self, id: u64) -> Option<&Task> {
-        self.tasks.get(&id)
+        self.data.tasks.get(&id)
+    }
+
+    pub fn get_task_by_uuid(&self, uuid: &Uuid) -> Option<&Task> {
+        self.data.tasks.values().find(|task| task.uuid == *uuid)
     }
 
-    pub fn complete_task(&mut self, id: u64) -> Result<()> {
-        if let Some(task) = self.tasks.get_mut(&id) {
+    /// Direct subtasks of `id`, ordered by id.
+    pub fn children(&self, id: u64) -> Vec<&Task> {
+        self.links
+            .children
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.data.tasks.get(id))
+            .collect()
+    }
+
+    /// Every subtask below `id`, depth first.
+    pub fn descendants(&self, id: u64) -> Vec<&Task> {
+        let mut descendants = Vec::new();
+        for child in self.children(id) {
+            descendants.push(child);
+            descendants.extend(self.descendants(child.id));
+        }
+        descendants
+    }
+
+    /// `(closed, total)` direct subtasks of `id`, or `None` if it has none.
+    pub fn subtask_progress(&self, id: u64) -> Option<(usize, usize)> {
+        let children = self.children(id);
+        if children.is_empty() {
+            return None;
+        }
+        let completed = children
+            .iter()
+            .filter(|task| task.status.is_closed())
+            .count();
+        Some((completed, children.len()))
+    }
+
+    /// Complete `id`. If it still has unfinished subtasks, either complete them
+    /// too (`cascade`) or refuse with `TodoError::PendingSubtasks`. A task
+    /// that is already completed is refused with `TodoError::AlreadyCompleted`.
+    ///
+    /// Every open recurring task this completes, `id` or a cascaded subtask,
+    /// gets its next occurrence. A subtask's occurrence goes under its
+    /// parent's next occurrence when the parent recurs too. Returns the ids
+    /// of the new occurrences, `id`'s own first.
+    pub fn complete_task(&mut self, id: u64, cascade: bool) -> Result<Vec<u64>> {
+        let before = self.task_snapshot(id)?;
+        if before.status == Status::Completed {
+            return Err(TodoError::AlreadyCompleted(id));
+        }
+        let pending: Vec<Task> = self
+            .descendants(id)
+            .into_iter()
+            .filter(|task| task.status.is_open())
+            .cloned()
+            .collect();
+        if !pending.is_empty() && !cascade {
+            return Err(TodoError::PendingSubtasks(id, pending.len()));
+        }
+
+        let mut changes = Vec::new();
+        let mut completed = Vec::new();
+        for subtask in pending {
+            let mut task = subtask.clone();
             task.complete();
-            self.save()?;
-            Ok(())
-        } else {
-            Err(TodoError::TaskNotFound(id))
+            changes.push(Change::new(subtask.id, Some(subtask), Some(task.clone())));
+            completed.push(task);
+        }
+        let mut task = before.clone();
+        task.complete();
+        changes.push(Change::new(id, Some(before.clone()), Some(task.clone())));
+        // A cancelled task being completed doesn't start a new cycle.
+        if before.status.is_open() {
+            completed.insert(0, task);
         }
+
+        // Parents come before their subtasks, so an occurrence's parent has
+        // been mapped by the time it is needed.
+        let mut occurrences: HashMap<u64, u64> = HashMap::new();
+        let mut next_ids = Vec::new();
+        for task in &completed {
+            let Some(mut next) = self.next_occurrence(task) else {
+                continue;
+            };
+            if let Some(parent) = task.parent_id.and_then(|p| occurrences.get(&p)) {
+                next.parent_id = Some(*parent);
+            }
+            occurrences.insert(task.id, next.id);
+            next_ids.push(next.id);
+            changes.push(Change::new(next.id, None, Some(next)));
+        }
+        self.commit(format!("complete task {id}"), changes)?;
+        Ok(next_ids)
     }
 
+    /// A fresh pending copy of the just-completed recurring `task`, due on
+    /// the next date its rule produces, if there is one.
+    fn next_occurrence(&mut self, task: &Task) -> Option<Task> {
+        let recurrence = task.recurrence.as_ref()?;
+        let completed_at = task.completed_at.unwrap_or_else(Utc::now);
+        let due = recurrence.next_due(task.due_date, completed_at, &self.zone)?;
+
+        let mut next = Task::new(self.data.next_id, task.name.clone());
+        self.data.next_id += 1;
+        next.priority = task.priority.clone();
+        next.category = task.category.clone();
+        next.tags = task.tags.clone();
+        next.parent_id = task.parent_id;
+        next.depends_on = task.depends_on.clone();
+        next.due_date = Some(due);
+        next.recurrence = Some(recurrence.clone());
+        Some(next)
+    }
+
+    /// Remove `id`. Its subtasks move up to its own parent and any
+    /// dependencies on it are dropped.
     pub fn remove_task(&mut self, id: u64) -> Result<()> {
-        if self.tasks.remove(&id).is_some() {
-            self.save()?;
-            Ok(())
-        } else {
-            Err(TodoError::TaskNotFound(id))
+        let before = self.task_snapshot(id)?;
+        let mut changes = Vec::new();
+        let mut affected: Vec<&Task> = self
+            .children(id)
+            .into_iter()
+            .chain(self.dependents(id))
+            .collect();
+        affected.sort_by_key(|task| task.id);
+        affected.dedup_by_key(|task| task.id);
+        for task in affected {
+            let mut updated = task.clone();
+            if updated.parent_id == Some(id) {
+                updated.parent_id = before.parent_id;
+            }
+            updated.depends_on.retain(|dep| *dep != id);
+            updated.update_at = Utc::now();
+            changes.push(Change::new(task.id, Some(task.clone()), Some(updated)));
+        }
+        changes.push(Change::new(id, Some(before), None));
+        self.commit(format!("remove task {id}"), changes)
+    }
+
+    /// Make `id` depend on each of `deps`. Fails without changing anything if
+    /// a dependency is missing or would create a cycle.
+    pub fn add_dependencies(&mut self, id: u64, deps: &[u64]) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        for &dep in deps {
+            if !self.data.tasks.contains_key(&dep) {
+                return Err(TodoError::TaskNotFound(dep));
+            }
+            if dep == id || self.depends_transitively(dep, id) {
+                return Err(TodoError::DependencyCycle(id, dep));
+            }
+            if !task.depends_on.contains(&dep) {
+                task.depends_on.push(dep);
+            }
+        }
+        task.depends_on.sort();
+        task.update_at = Utc::now();
+        self.commit(
+            format!("add dependencies to task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
+    }
+
+    pub fn remove_dependencies(&mut self, id: u64, deps: &[u64]) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        task.depends_on.retain(|dep| !deps.contains(dep));
+        task.update_at = Utc::now();
+        self.commit(
+            format!("remove dependencies from task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
+    }
+
+    /// Whether `from` depends on `to`, directly or through other tasks.
+    fn depends_transitively(&self, from: u64, to: u64) -> bool {
+        let mut stack = vec![from];
+        let mut seen = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if seen.insert(id)
+                && let Some(task) = self.data.tasks.get(&id)
+            {
+                stack.extend(&task.depends_on);
+            }
+        }
+        false
+    }
+
+    /// Unfinished tasks that `id` depends on.
+    pub fn blockers(&self, id: u64) -> Vec<&Task> {
+        let Some(task) = self.data.tasks.get(&id) else {
+            return Vec::new();
+        };
+        task.depends_on
+            .iter()
+            .filter_map(|dep| self.data.tasks.get(dep))
+            .filter(|dep| dep.status.is_open())
+            .collect()
+    }
+
+    /// A task is blocked while any of its dependencies is unfinished.
+    pub fn is_blocked(&self, id: u64) -> bool {
+        !self.blockers(id).is_empty()
+    }
+
+    /// Tasks that depend on `id`, ordered by id.
+    pub fn dependents(&self, id: u64) -> Vec<&Task> {
+        self.links
+            .dependents
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.data.tasks.get(id))
+            .collect()
+    }
+
+    /// Whether any open task depends on `id`.
+    pub fn is_blocking(&self, id: u64) -> bool {
+        self.dependents(id).iter().any(|task| task.status.is_open())
+    }
+
+    /// `Task::urgency` of `task` with the manager's coefficients, clock and
+    /// dependency graph.
+    pub fn urgency(&self, task: &Task) -> f64 {
+        task.urgency(
+            &self.urgency,
+            self.now(),
+            self.is_blocking(task.id),
+            self.is_blocked(task.id),
+        )
+    }
+
+    /// Pending or in-progress tasks that are neither blocked nor hidden by
+    /// a wait date, most urgent first.
+    pub fn next_tasks(&self) -> Vec<&Task> {
+        let now = self.now();
+        let mut tasks: Vec<(f64, &Task)> = self
+            .list_tasks()
+            .into_iter()
+            .filter(|task| {
+                matches!(task.status, Status::Pending | Status::InProgress)
+                    && !task.is_waiting(now)
+                    && !self.is_blocked(task.id)
+            })
+            .map(|task| (self.urgency(task), task))
+            .collect();
+        tasks.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.id.cmp(&b.1.id)));
+        tasks.into_iter().map(|(_, task)| task).collect()
+    }
+
+    pub fn set_notes(&mut self, id: u64, notes: String) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        task.notes = notes;
+        task.update_at = Utc::now();
+        self.commit(
+            format!("edit notes of task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
+    }
+
+    pub fn annotate(&mut self, id: u64, text: String) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        task.annotate(text);
+        self.commit(
+            format!("annotate task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
+    }
+
+    /// Remove one annotation from `id`; see `Task::denotate`.
+    pub fn denotate(&mut self, id: u64, pattern: Option<&str>) -> Result<Annotation> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        let removed = task.denotate(pattern).ok_or_else(|| match pattern {
+            Some(pattern) => TodoError::AnnotationNotFound(id, pattern.to_string()),
+            None => TodoError::NoAnnotations(id),
+        })?;
+        self.commit(
+            format!("denotate task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )?;
+        Ok(removed)
+    }
+
+    /// The task whose timer is running, if any.
+    pub fn running_timer(&self) -> Option<&Task> {
+        self.data.tasks.values().find(|task| task.is_tracking())
+    }
+
+    /// Start tracking time on `id`. Only one timer may run at a time. A
+    /// pending task is moved to in progress.
+    pub fn start_timer(&mut self, id: u64) -> Result<()> {
+        if let Some(running) = self.running_timer() {
+            return Err(TodoError::TimerRunning(running.id));
+        }
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        if task.status == Status::Pending {
+            task.set_status(Status::InProgress);
         }
+        task.sessions.push(TimeSession {
+            start: Utc::now(),
+            end: None,
+        });
+        task.update_at = Utc::now();
+        self.commit(
+            format!("start timer on task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )
+    }
+
+    /// Stop the running timer. Returns the task it ran on and the length of
+    /// the session.
+    pub fn stop_timer(&mut self) -> Result<(u64, Duration)> {
+        let before = self
+            .running_timer()
+            .cloned()
+            .ok_or(TodoError::NoTimerRunning)?;
+        let id = before.id;
+        let mut task = before.clone();
+        let spent = task.stop_tracking(Utc::now()).unwrap_or_default();
+        self.commit(
+            format!("stop timer on task {id}"),
+            vec![Change::new(id, Some(before), Some(task))],
+        )?;
+        Ok((id, spent))
+    }
+
+    /// Move `id` to `status`. Use `complete_task` to complete a task, so
+    /// subtasks and recurrence are handled.
+    pub fn set_status(&mut self, id: u64, status: Status) -> Result<()> {
+        let before = self.task_snapshot(id)?;
+        let mut task = before.clone();
+        let action = format!("set task {id} to {status}");
+        task.set_status(status);
+        self.commit(action, vec![Change::new(id, Some(before), Some(task))])
+    }
+
+    /// Revert the most recent operation that has not been undone yet.
+    pub fn undo(&mut self) -> Result<JournalEntry> {
+        let entry = self
+            .journal
+            .next_undo()?
+            .cloned()
+            .ok_or(TodoError::NothingToUndo)?;
+        let changes: Vec<Change> = entry.changes.iter().rev().map(Change::inverse).collect();
+        self.check_unchanged(&changes)?;
+        self.apply(&changes)?;
+        let undo =
+            self.journal
+                .append(entry.action, EntryKind::Undo { target: entry.seq }, changes)?;
+        Ok(undo.clone())
+    }
+
+    /// Re-apply the most recently undone operation.
+    pub fn redo(&mut self) -> Result<JournalEntry> {
+        let entry = self
+            .journal
+            .next_redo()?
+            .cloned()
+            .ok_or(TodoError::NothingToRedo)?;
+        self.check_unchanged(&entry.changes)?;
+        self.apply(&entry.changes)?;
+        let redo = self.journal.append(
+            entry.action,
+            EntryKind::Redo { target: entry.seq },
+            entry.changes,
+        )?;
+        Ok(redo.clone())
+    }
+
+    /// Make sure every task `changes` touches is still in the state it
+    /// starts from, so replaying the journal can't overwrite a change made
+    /// some other way (or recorded against other data).
+    fn check_unchanged(&self, changes: &[Change]) -> Result<()> {
+        let mut seen = HashSet::new();
+        for change in changes {
+            if seen.insert(change.id) && self.data.tasks.get(&change.id) != change.before.as_ref() {
+                return Err(TodoError::JournalMismatch(change.id));
+            }
+        }
+        Ok(())
+    }
+
+    fn task_snapshot(&self, id: u64) -> Result<Task> {
+        self.data
+            .tasks
+            .get(&id)
+            .cloned()
+            .ok_or(TodoError::TaskNotFound(id))
+    }
+
+    /// Bring the tasks named by `changes` to their `after` state, in memory
+    /// and then in storage with a single write. If storage fails, the
+    /// in-memory tasks are put back as they were.
+    fn apply(&mut self, changes: &[Change]) -> Result<()> {
+        if self.partial {
+            return Err(TodoError::PartiallyLoaded);
+        }
+        let next_id = self.data.next_id;
+        for change in changes {
+            self.apply_in_memory(change);
+        }
+        if let Err(e) = self.storage.commit(&self.data, changes) {
+            for change in changes.iter().rev() {
+                self.apply_in_memory(&change.inverse());
+            }
+            self.data.next_id = next_id;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn apply_in_memory(&mut self, change: &Change) {
+        if let Some(task) = self.data.tasks.remove(&change.id) {
+            self.links.remove(&task);
+        }
+        if let Some(task) = &change.after {
+            self.links.add(task);
+            self.data.upsert(task.clone());
+        }
+    }
+
+    /// Apply `changes` and journal them as a single undoable operation.
+    fn commit(&mut self, action: String, changes: Vec<Change>) -> Result<()> {
+        self.apply(&changes)?;
+        self.journal.append(action, EntryKind::Apply, changes)?;
+        Ok(())
     }
 
     pub fn get_tasks_by_category(&self, category: &str) -> Vec<&Task> {
-        self.tasks
+        self.data
+            .tasks
             .values()
             .filter(|task| task.category == category)
             .collect()
@@ -156,6 +856,7 @@ impl TodoManager {
 
     pub fn get_categories(&self) -> Vec<String> {
         let mut categories: Vec<String> = self
+            .data
             .tasks
             .values()
             .map(|task| task.category.clone())
@@ -164,38 +865,217 @@ impl TodoManager {
         categories.dedup();
         categories
     }
+}
 
-    fn save(&self) -> Result<()> {
-        let path = self.data_path()?;
-        let data = serde_json::to_string_pretty(&self.tasks)?;
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::write(path, data)?;
-        Ok(())
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::storage::MemoryStorage;
+    use crate::core::testing;
+    use crate::core::time::FixedClock;
+    use chrono::NaiveDate;
 
-    fn parse_due_date(due: &str) -> Result<Option<DateTime<Utc>>> {
-        if due.is_empty() {
-            return Ok(None);
-        }
+    /// A manager whose clock is stopped at `testing::now`.
+    fn manager() -> TodoManager {
+        TodoManager::with_storage(MemoryStorage::new())
+            .unwrap()
+            .with_clock(FixedClock(testing::now()))
+    }
 
-        if due.starts_with('+') {
-            if let Ok(days) = due[1..].trim_end_matches("days").trim().parse::<i64>() {
-                let due_date = Utc::now() + Duration::days(days);
-                return Ok(Some(due_date));
-            }
+    fn due(due: &str) -> TaskOptions {
+        TaskOptions {
+            due: Some(due.to_string()),
+            ..TaskOptions::default()
         }
+    }
 
-        if let Ok(native_date) = NaiveDate::parse_from_str(due, "%Y-%m-%d") {
-            let due_date = native_date
-                .and_hms_opt(23, 59, 59)
+    #[test]
+    fn relative_dates_follow_the_clock() {
+        let mut manager = manager();
+        let id = manager.add_task("report", due("tomorrow 3pm")).unwrap();
+        let task = manager.get_task(id).unwrap();
+        let expected = manager.zone().resolve(
+            NaiveDate::from_ymd_opt(2025, 6, 5)
                 .unwrap()
-                .and_local_timezone(Utc)
-                .unwrap();
-            return Ok(Some(due_date));
+                .and_hms_opt(15, 0, 0)
+                .unwrap(),
+        );
+        assert_eq!(task.due_date, Some(expected));
+        assert_eq!(task.days_until_due(manager.now(), manager.zone()), Some(1));
+        assert!(!task.is_overdue(manager.now()));
+        assert!(task.is_overdue(manager.now() + Duration::days(2)));
+    }
+
+    #[test]
+    fn next_skips_tasks_waiting_past_the_clock() {
+        let mut manager = manager();
+        let waiting = TaskOptions {
+            wait: Some("friday".to_string()),
+            ..TaskOptions::default()
+        };
+        manager.add_task("later", waiting).unwrap();
+        let now = manager.add_task("now", TaskOptions::default()).unwrap();
+        let next: Vec<u64> = manager.next_tasks().iter().map(|task| task.id).collect();
+        assert_eq!(next, vec![now]);
+    }
+
+    #[test]
+    fn next_puts_the_most_urgent_first() {
+        let mut manager = manager();
+        let low = TaskOptions {
+            priority: Some(Priority::Low),
+            ..TaskOptions::default()
+        };
+        let chore = manager.add_task("chore", low.clone()).unwrap();
+        let soon = manager.add_task("soon", due("tomorrow")).unwrap();
+        let unblocks = manager.add_task("unblocks", low).unwrap();
+        let blocked = manager.add_task("blocked", TaskOptions::default()).unwrap();
+        manager.add_dependencies(blocked, &[unblocks]).unwrap();
+
+        let next: Vec<u64> = manager.next_tasks().iter().map(|task| task.id).collect();
+        assert_eq!(next, vec![soon, unblocks, chore]);
+
+        let urgent = UrgencyCoefficients {
+            low: 20.0,
+            ..UrgencyCoefficients::default()
+        };
+        let manager = manager.with_urgency(urgent);
+        let next: Vec<u64> = manager.next_tasks().iter().map(|task| task.id).collect();
+        assert_eq!(next, vec![unblocks, chore, soon]);
+    }
+
+    #[test]
+    fn subtasks_and_dependents_follow_changes() {
+        let mut manager = manager();
+        let root = manager.add_task("root", TaskOptions::default()).unwrap();
+        let parent = TaskOptions {
+            parent: Some(root),
+            ..TaskOptions::default()
+        };
+        let middle = manager.add_task("middle", parent).unwrap();
+        let parent = TaskOptions {
+            parent: Some(middle),
+            ..TaskOptions::default()
+        };
+        let leaf = manager.add_task("leaf", parent).unwrap();
+        manager.add_dependencies(root, &[leaf]).unwrap();
+        assert!(manager.is_blocking(leaf));
+
+        manager.remove_task(middle).unwrap();
+        assert_eq!(manager.subtask_progress(root), Some((0, 1)));
+        assert_eq!(manager.get_task(leaf).unwrap().parent_id, Some(root));
+
+        manager.complete_task(leaf, false).unwrap();
+        assert_eq!(manager.subtask_progress(root), Some((1, 1)));
+        assert!(!manager.is_blocked(root));
+
+        manager.undo().unwrap();
+        manager.undo().unwrap();
+        assert_eq!(manager.subtask_progress(root), Some((0, 1)));
+        assert_eq!(manager.subtask_progress(middle), Some((0, 1)));
+        assert!(manager.is_blocked(root));
+    }
+
+    #[test]
+    fn closing_a_task_stops_its_timer() {
+        let mut manager = manager();
+        let first = manager.add_task("first", TaskOptions::default()).unwrap();
+        let second = manager.add_task("second", TaskOptions::default()).unwrap();
+
+        manager.start_timer(first).unwrap();
+        manager.complete_task(first, false).unwrap();
+        assert!(manager.running_timer().is_none());
+        assert!(!manager.get_task(first).unwrap().is_tracking());
+
+        manager.start_timer(second).unwrap();
+        manager.set_status(second, Status::Cancelled).unwrap();
+        assert!(manager.running_timer().is_none());
+
+        manager.undo().unwrap();
+        assert_eq!(manager.running_timer().map(|task| task.id), Some(second));
+    }
+
+    #[test]
+    fn completing_recurring_tasks_spawns_one_occurrence_each() {
+        let mut manager = manager();
+        let daily = TaskOptions {
+            recurrence: Some(Recurrence::Daily),
+            ..due("today")
+        };
+        let parent = manager.add_task("standup", daily.clone()).unwrap();
+        let child = TaskOptions {
+            parent: Some(parent),
+            ..daily
+        };
+        let notes = manager.add_task("notes", child).unwrap();
+
+        let next = manager.complete_task(parent, true).unwrap();
+        assert_eq!(next.len(), 2);
+        let next_notes = manager.get_task(next[1]).unwrap();
+        assert_eq!(next_notes.name, "notes");
+        assert_eq!(next_notes.parent_id, Some(next[0]));
+        assert_eq!(manager.get_task(notes).unwrap().status, Status::Completed);
+
+        let err = manager.complete_task(parent, false).unwrap_err();
+        assert!(matches!(err, TodoError::AlreadyCompleted(id) if id == parent));
+        assert_eq!(manager.list_tasks().len(), 4);
+    }
+
+    #[test]
+    fn undo_refuses_entries_that_no_longer_match() {
+        let path = std::env::temp_dir().join(format!("todo-test-{}.jsonl", Uuid::new_v4()));
+        let mut first = manager().with_journal(Journal::open(&path));
+        first.add_task("first", TaskOptions::default()).unwrap();
+
+        // Another manager, on other data, sharing the journal file.
+        let mut second = manager().with_journal(Journal::open(&path));
+        let err = second.undo().unwrap_err();
+        assert!(matches!(err, TodoError::JournalMismatch(1)), "{err}");
+        assert!(second.list_tasks().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// `tasks` as a version 0 file stored them: a bare map, without uuids.
+    fn v0(tasks: &[Task]) -> Value {
+        let mut map = serde_json::Map::new();
+        for task in tasks {
+            let mut task = serde_json::to_value(task).unwrap();
+            task.as_object_mut().unwrap().remove("uuid");
+            map.insert(task["id"].to_string(), task);
         }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn migrates_a_bare_task_map() {
+        let tasks = [Task::new(3, "a".to_string()), Task::new(7, "b".to_string())];
+        let (data, from) = migrate(v0(&tasks)).unwrap();
+        assert_eq!(from, Some(0));
+        assert_eq!(data.version, SCHEMA_VERSION);
+        assert_eq!(data.next_id, 8);
+        assert_eq!(data.tasks.len(), 2);
+        assert_ne!(data.tasks[&3].uuid, data.tasks[&7].uuid);
+
+        let (again, from) = migrate(serde_json::to_value(&data).unwrap()).unwrap();
+        assert_eq!(from, None);
+        assert_eq!(again.tasks[&3].uuid, data.tasks[&3].uuid);
+    }
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let newer = json!({"version": SCHEMA_VERSION + 1, "next_id": 1, "tasks": {}});
+        let err = migrate(newer).unwrap_err();
+        assert!(matches!(err, TodoError::UnsupportedSchema(v) if v == SCHEMA_VERSION + 1));
+    }
 
-        Err(TodoError::InvalidDate(due.to_string()))
+    #[test]
+    fn removed_ids_are_not_reused() {
+        let mut manager = manager();
+        manager.add_task("first", TaskOptions::default()).unwrap();
+        let last = manager.add_task("last", TaskOptions::default()).unwrap();
+        manager.remove_task(last).unwrap();
+        let next = manager.add_task("next", TaskOptions::default()).unwrap();
+        assert_eq!(next, last + 1);
     }
 }
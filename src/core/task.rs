@@ -1,38 +1,188 @@
-use chrono::{DateTime, Utc};
+use crate::core::recurrence::Recurrence;
+use crate::core::time::Zone;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Task {
     pub id: u64,
+    /// Stable identity that survives renumbering, imports and merges.
+    pub uuid: Uuid,
     pub name: String,
+    /// Free-form, possibly multi-line description.
+    #[serde(default)]
+    pub notes: String,
     pub status: Status,
     pub priority: Priority,
     pub category: String,
     pub create_at: DateTime<Utc>,
     pub update_at: DateTime<Utc>,
+    /// When work first started (the first move to `InProgress`).
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// Set while the task is `Completed`, cleared by any other status.
     pub completed_at: Option<DateTime<Utc>>,
     pub due_date: Option<DateTime<Utc>>,
+    /// When work on the task is planned to start.
+    #[serde(default)]
+    pub scheduled: Option<DateTime<Utc>>,
+    /// The task is hidden from `list` until this date.
+    #[serde(default)]
+    pub wait_until: Option<DateTime<Utc>>,
     pub tags: Vec<String>,
+    /// The task this one is a subtask of.
+    #[serde(default)]
+    pub parent_id: Option<u64>,
+    /// Tasks that must be completed before this one can start.
+    #[serde(default)]
+    pub depends_on: Vec<u64>,
+    /// Completing a recurring task creates its next occurrence.
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Timestamped comments, oldest first.
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    /// Time-tracking sessions, oldest first. At most one is running.
+    #[serde(default)]
+    pub sessions: Vec<TimeSession>,
+    #[serde(default)]
+    pub estimate: Option<Estimate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Annotation {
+    pub at: DateTime<Utc>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TimeSession {
+    pub start: DateTime<Utc>,
+    /// `None` while the timer is running.
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Expected effort, either as a duration or in story points.
+///
+/// Parsed from `2h`, `30m`, `1h30m`, or `3pts` / `3sp`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Estimate {
+    Minutes(u32),
+    Points(u32),
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Estimate::Minutes(minutes) if minutes % 60 == 0 => write!(f, "{}h", minutes / 60),
+            Estimate::Minutes(minutes) if *minutes > 60 => {
+                write!(f, "{}h{}m", minutes / 60, minutes % 60)
+            }
+            Estimate::Minutes(minutes) => write!(f, "{}m", minutes),
+            Estimate::Points(1) => write!(f, "1pt"),
+            Estimate::Points(points) => write!(f, "{}pts", points),
+        }
+    }
+}
+
+impl std::str::FromStr for Estimate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let invalid = || {
+            format!(
+                "Invalid estimate '{}' (expected e.g. 2h, 30m, 1h30m or 3pts)",
+                s
+            )
+        };
+
+        for suffix in ["points", "pts", "pt", "sp"] {
+            if let Some(points) = s.strip_suffix(suffix) {
+                return points
+                    .trim()
+                    .parse()
+                    .map(Estimate::Points)
+                    .map_err(|_| invalid());
+            }
+        }
+
+        let (hours, rest) = match s.split_once('h') {
+            Some((hours, rest)) => (hours.trim().parse::<u32>().map_err(|_| invalid())?, rest),
+            None => (0, s.as_str()),
+        };
+        let minutes = match rest.trim() {
+            "" if s.contains('h') => 0,
+            rest => rest
+                .strip_suffix('m')
+                .and_then(|minutes| minutes.trim().parse::<u32>().ok())
+                .ok_or_else(invalid)?,
+        };
+        match hours
+            .checked_mul(60)
+            .and_then(|hours| hours.checked_add(minutes))
+        {
+            None | Some(0) => Err(invalid()),
+            Some(total) => Ok(Estimate::Minutes(total)),
+        }
+    }
+}
+
+impl TimeSession {
+    /// Time spent in this session between `from` and `to`; a running
+    /// session counts up to `to`.
+    pub fn overlap(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Duration {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(to).min(to);
+        if end > start {
+            end - start
+        } else {
+            Duration::zero()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Status {
     Pending,
+    InProgress,
+    Waiting,
+    Blocked,
     Completed,
+    Cancelled,
+}
+
+impl Status {
+    /// Still needs work: anything but completed or cancelled.
+    pub fn is_open(&self) -> bool {
+        !self.is_closed()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Status::Completed | Status::Cancelled)
+    }
 }
 
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Status::Pending => write!(f, "Pending"),
+            Status::InProgress => write!(f, "In Progress"),
+            Status::Waiting => write!(f, "Waiting"),
+            Status::Blocked => write!(f, "Blocked"),
             Status::Completed => write!(f, "Completed"),
+            Status::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Ordered from least to most important.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Priority {
     Low,
     Medium,
@@ -59,32 +209,164 @@ impl std::str::FromStr for Priority {
             "low" | "l" => Ok(Priority::Low),
             "medium" | "med" => Ok(Priority::Medium),
             "high" | "h" => Ok(Priority::High),
+            "critical" | "crit" | "c" => Ok(Priority::Critical),
             _ => Err("Invalid priority".to_string()),
         }
     }
 }
 
+/// Weights of the terms that make up `Task::urgency`. Every key can be
+/// overridden in the `[urgency]` table of config.toml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UrgencyCoefficients {
+    pub critical: f64,
+    pub high: f64,
+    pub medium: f64,
+    pub low: f64,
+    /// Scaled from 0.2 (due in two weeks or more) to 1.0 (a week overdue).
+    pub due: f64,
+    /// Added on top of `due` once the due date has passed.
+    pub overdue: f64,
+    /// Scaled by age, reaching the full value after `max_age_days`.
+    pub age: f64,
+    pub max_age_days: f64,
+    /// Work already in progress.
+    pub active: f64,
+    /// Other open tasks depend on this one.
+    pub blocking: f64,
+    /// Waiting on unfinished dependencies.
+    pub blocked: f64,
+    /// Hidden by a wait date, or in the waiting status.
+    pub waiting: f64,
+    /// Extra weight per tag, e.g. `tags = { urgent = 5.0 }`.
+    pub tags: HashMap<String, f64>,
+}
+
+impl Default for UrgencyCoefficients {
+    fn default() -> Self {
+        Self {
+            critical: 9.0,
+            high: 6.0,
+            medium: 3.9,
+            low: 1.8,
+            due: 12.0,
+            overdue: 4.0,
+            age: 2.0,
+            max_age_days: 365.0,
+            active: 4.0,
+            blocking: 8.0,
+            blocked: -5.0,
+            waiting: -3.0,
+            tags: HashMap::from([("urgent".to_string(), 5.0)]),
+        }
+    }
+}
+
 impl Task {
+    /// How pressing the task is at `now`: the higher, the sooner it should
+    /// be worked on. Closed tasks score 0. `blocking` and `blocked` describe
+    /// the task's open dependency relationships, which only the manager
+    /// knows about.
+    pub fn urgency(
+        &self,
+        coefficients: &UrgencyCoefficients,
+        now: DateTime<Utc>,
+        blocking: bool,
+        blocked: bool,
+    ) -> f64 {
+        if self.status.is_closed() {
+            return 0.0;
+        }
+        let mut urgency = match self.priority {
+            Priority::Critical => coefficients.critical,
+            Priority::High => coefficients.high,
+            Priority::Medium => coefficients.medium,
+            Priority::Low => coefficients.low,
+        };
+
+        if let Some(due) = self.due_date {
+            let days_overdue = (now - due).num_minutes() as f64 / (24.0 * 60.0);
+            let proximity = if days_overdue >= 7.0 {
+                1.0
+            } else if days_overdue >= -14.0 {
+                0.2 + (days_overdue + 14.0) * 0.8 / 21.0
+            } else {
+                0.2
+            };
+            urgency += coefficients.due * proximity;
+            if due < now {
+                urgency += coefficients.overdue;
+            }
+        }
+
+        if coefficients.max_age_days > 0.0 {
+            let age = (now - self.create_at).num_days() as f64 / coefficients.max_age_days;
+            urgency += coefficients.age * age.clamp(0.0, 1.0);
+        }
+
+        if self.status == Status::InProgress {
+            urgency += coefficients.active;
+        }
+        if self.status == Status::Waiting || self.is_waiting(now) {
+            urgency += coefficients.waiting;
+        }
+        if blocking {
+            urgency += coefficients.blocking;
+        }
+        if blocked {
+            urgency += coefficients.blocked;
+        }
+        for tag in &self.tags {
+            urgency += coefficients.tags.get(tag).copied().unwrap_or(0.0);
+        }
+        urgency
+    }
+
     pub fn new(id: u64, name: String) -> Self {
         let now = Utc::now();
         Self {
             id,
+            uuid: Uuid::new_v4(),
             name,
+            notes: String::new(),
             status: Status::Pending,
             priority: Priority::Medium,
             category: "General".to_string(),
             create_at: now,
             update_at: now,
+            started_at: None,
             completed_at: None,
             due_date: None,
+            scheduled: None,
+            wait_until: None,
             tags: vec![],
+            parent_id: None,
+            depends_on: vec![],
+            recurrence: None,
+            annotations: vec![],
+            sessions: vec![],
+            estimate: None,
         }
     }
 
     pub fn complete(&mut self) {
-        self.status = Status::Completed;
-        self.completed_at = Some(Utc::now());
-        self.update_at = Utc::now();
+        self.set_status(Status::Completed);
+    }
+
+    /// Move to `status`, keeping `started_at` and `completed_at` in step.
+    /// Closing the task stops its timer.
+    pub fn set_status(&mut self, status: Status) {
+        let now = Utc::now();
+        if status == Status::InProgress && self.started_at.is_none() {
+            self.started_at = Some(now);
+        }
+        if status.is_closed() {
+            self.stop_tracking(now);
+        }
+        self.completed_at = (status == Status::Completed).then_some(now);
+        self.status = status;
+        self.update_at = now;
     }
 
     pub fn set_priority(&mut self, priority: Priority) {
@@ -109,20 +391,91 @@ impl Task {
         }
     }
 
-    pub fn is_overdue(&self) -> bool {
+    pub fn annotate(&mut self, text: String) {
+        let now = Utc::now();
+        self.annotations.push(Annotation { at: now, text });
+        self.update_at = now;
+    }
+
+    /// Remove the annotation whose text is `pattern`, or failing that the
+    /// only one containing it. Without a pattern the newest one is removed.
+    pub fn denotate(&mut self, pattern: Option<&str>) -> Option<Annotation> {
+        let index = match pattern {
+            None => self.annotations.len().checked_sub(1)?,
+            Some(pattern) => self
+                .annotations
+                .iter()
+                .position(|a| a.text == pattern)
+                .or_else(|| {
+                    let mut matches = self
+                        .annotations
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, a)| a.text.contains(pattern));
+                    match (matches.next(), matches.next()) {
+                        (Some((index, _)), None) => Some(index),
+                        _ => None,
+                    }
+                })?,
+        };
+        self.update_at = Utc::now();
+        Some(self.annotations.remove(index))
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.sessions.iter().any(|session| session.end.is_none())
+    }
+
+    /// End the running session at `at`. Returns how long it ran, or `None`
+    /// if no timer was running.
+    pub fn stop_tracking(&mut self, at: DateTime<Utc>) -> Option<Duration> {
+        let session = self.sessions.iter_mut().find(|s| s.end.is_none())?;
+        session.end = Some(at);
+        self.update_at = at;
+        Some(at - session.start)
+    }
+
+    /// Total tracked time; a running session counts up to now.
+    pub fn tracked_time(&self) -> Duration {
+        self.tracked_between(DateTime::<Utc>::MIN_UTC, Utc::now())
+    }
+
+    /// Tracked time that falls between `from` and `to`.
+    pub fn tracked_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Duration {
+        self.sessions
+            .iter()
+            .map(|session| session.overlap(from, to))
+            .fold(Duration::zero(), |total, spent| total + spent)
+    }
+
+    /// Time actually spent: tracked time if any was tracked, otherwise the
+    /// time from creation to completion. `None` for unfinished, untracked tasks.
+    pub fn actual_time(&self) -> Option<Duration> {
+        if !self.sessions.is_empty() {
+            return Some(self.tracked_time());
+        }
+        self.completed_at
+            .map(|completed| completed - self.create_at)
+    }
+
+    /// Whether the task is still hidden by its wait date at `now`.
+    pub fn is_waiting(&self, now: DateTime<Utc>) -> bool {
+        self.wait_until.is_some_and(|wait| wait > now)
+    }
+
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
         if let Some(due) = self.due_date {
-            self.status == Status::Pending && due < Utc::now()
+            self.status.is_open() && due < now
         } else {
             false
         }
     }
 
-    pub fn days_until_due(&self) -> Option<i64> {
-        self.due_date.map(|due| {
-            let now = Utc::now();
-            let duration = due - now;
-            duration.num_days()
-        })
+    /// Calendar days from `now` to the due date in the configured zone, so
+    /// anything due later today is 0 and anything due tomorrow is 1.
+    pub fn days_until_due(&self, now: DateTime<Utc>, zone: &Zone) -> Option<i64> {
+        self.due_date
+            .map(|due| (zone.date_of(due) - zone.date_of(now)).num_days())
     }
 }
 
@@ -130,7 +483,11 @@ impl Display for Task {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let status_icon = match self.status {
             Status::Pending => "  ",
+            Status::InProgress => "▶️",
+            Status::Waiting => "⏳",
+            Status::Blocked => "⛔",
             Status::Completed => "✅️",
+            Status::Cancelled => "❌",
         };
         let priority_icon = match self.priority {
             Priority::Low => "⬇",
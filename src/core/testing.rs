@@ -0,0 +1,14 @@
+//! Fixtures shared by the unit tests in `core`.
+
+use crate::core::time::Zone;
+use chrono::{DateTime, TimeZone, Utc};
+
+/// The moment tests treat as now: Wednesday, 4 June 2025, 10:00 UTC.
+pub fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 6, 4, 10, 0, 0).unwrap()
+}
+
+/// Dates in UTC, so results don't depend on the machine's zone.
+pub fn utc() -> Zone {
+    Zone::Named(chrono_tz::UTC)
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+
+/// Source of "now" for date logic: relative dates, overdue and due-today
+/// checks, wait dates.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always the same instant; used by tests and `--as-of`.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// The time zone dates are entered and shown in. Instants are always stored
+/// in UTC; the zone only decides what "today" and "14:00" mean.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// The operating system's zone.
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    /// Wall-clock time of `at` in this zone.
+    pub fn localize(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => at.with_timezone(&Local).naive_local(),
+            Zone::Named(tz) => at.with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// The instant a wall-clock time in this zone refers to. Ambiguous
+    /// times (DST fall-back) take the earlier instant; times skipped by a
+    /// DST jump move forward by an hour.
+    pub fn resolve(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        let resolved = match self {
+            Zone::Local => Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|at| at.with_timezone(&Utc)),
+            Zone::Named(tz) => tz
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|at| at.with_timezone(&Utc)),
+        };
+        resolved.unwrap_or_else(|| self.resolve(local + Duration::hours(1)))
+    }
+
+    /// The calendar day `at` falls on in this zone.
+    pub fn date_of(&self, at: DateTime<Utc>) -> NaiveDate {
+        self.localize(at).date()
+    }
+
+    /// Start (inclusive) and end (exclusive) of `day` in this zone.
+    pub fn day_bounds(&self, day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = self.resolve(day.and_time(NaiveTime::MIN));
+        let end = self.resolve(day.succ_opt().unwrap_or(day).and_time(NaiveTime::MIN));
+        (start, end)
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Local => write!(f, "local"),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        s.parse::<Tz>().map(Zone::Named).map_err(|_| {
+            format!(
+                "Unknown time zone '{}' (expected e.g. Europe/Berlin or local)",
+                s
+            )
+        })
+    }
+}
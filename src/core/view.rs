@@ -0,0 +1,305 @@
+//! Saved `list` invocations, run by name with `todo report <name>`.
+//!
+//! A view holds the same options as `todo list`: which tasks to show, in
+//! what order, which columns and how to group them. User views live in
+//! `[reports.<name>]` tables of config.toml, written in the same terms as
+//! the command line:
+//!
+//! ```toml
+//! [reports.work]
+//! status = "pending"
+//! category = "work"
+//! filter = "priority>=high"
+//! sort = "due,-priority"
+//! columns = "id,name,due,priority"
+//! group_by = "tag"
+//! ```
+
+use crate::core::dates;
+use crate::core::filter::{Filter, StatusMatch};
+use crate::core::group::GroupBy;
+use crate::core::sort::SortOrder;
+use crate::core::storage::TaskQuery;
+use crate::core::store::TodoManager;
+use crate::core::task::{Priority, Status, Task};
+use crate::core::time::Zone;
+use crate::error::{Result, TodoError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Reports that exist without being configured. A view of the same name
+/// in config.toml takes their place.
+pub const BUILTIN: &[(&str, &str)] = &[
+    (
+        "next",
+        "Open tasks that can be worked on now, most urgent first",
+    ),
+    ("overdue", "Open tasks past their due date, oldest first"),
+    ("waiting", "Tasks hidden until a wait date, soonest first"),
+    (
+        "completed-this-week",
+        "Tasks completed since Monday, most recent first",
+    ),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `None` shows every status.
+    #[serde(with = "text", skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusMatch>,
+    #[serde(with = "text", skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub overdue: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub today: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub blocked: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub unblocked: bool,
+    /// A day, kept as typed so `today` stays relative.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub waiting: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub include_waiting: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// `None` uses the default order.
+    #[serde(with = "text", skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortOrder>,
+    #[serde(with = "text", skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Columns>,
+    #[serde(with = "text", skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<GroupBy>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub tree: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub annotations: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl View {
+    /// One of the `BUILTIN` reports.
+    pub fn builtin(name: &str) -> Option<View> {
+        let (_, description) = BUILTIN.iter().find(|(builtin, _)| *builtin == name)?;
+        let view = match name {
+            "next" => View {
+                filter: Some("status:pending or status:in-progress".to_string()),
+                unblocked: true,
+                sort: Some("-urgency".parse().unwrap()),
+                ..View::default()
+            },
+            "overdue" => View {
+                overdue: true,
+                sort: Some("due,-priority".parse().unwrap()),
+                ..View::default()
+            },
+            "waiting" => View {
+                waiting: true,
+                sort: Some("wait".parse().unwrap()),
+                ..View::default()
+            },
+            _ => View {
+                status: Some(StatusMatch::Is(Status::Completed)),
+                filter: Some("completed>=sow".to_string()),
+                sort: Some("-completed".parse().unwrap()),
+                ..View::default()
+            },
+        };
+        Some(View {
+            description: Some(description.to_string()),
+            ..view
+        })
+    }
+
+    /// The part of the view a storage backend can select by itself.
+    pub fn query(&self, now: DateTime<Utc>, zone: &Zone) -> TaskQuery {
+        let mut query = TaskQuery {
+            status: match &self.status {
+                Some(StatusMatch::Is(status)) => Some(status.clone()),
+                _ => None,
+            },
+            priority: self.priority.clone(),
+            category: self.category.clone(),
+            ..TaskQuery::default()
+        };
+        if self.today {
+            let (start, end) = zone.day_bounds(zone.date_of(now));
+            query.due_after = Some(start);
+            query.due_before = Some(end);
+        }
+        query
+    }
+
+    /// The tasks the view shows, in its order.
+    pub fn tasks<'a>(&self, manager: &'a TodoManager) -> Result<Vec<&'a Task>> {
+        let now = manager.now();
+        let zone = manager.zone();
+
+        let mut tasks = manager.query_tasks(&self.query(now, zone));
+
+        match self.status {
+            Some(StatusMatch::Open) => tasks.retain(|task| task.status.is_open()),
+            Some(StatusMatch::Closed) => tasks.retain(|task| task.status.is_closed()),
+            _ => {}
+        }
+
+        if self.overdue {
+            tasks.retain(|task| task.is_overdue(now));
+        }
+
+        if self.blocked || self.unblocked {
+            tasks.retain(|task| manager.is_blocked(task.id) == self.blocked);
+        }
+
+        if let Some(day) = &self.scheduled {
+            let day =
+                dates::parse_day(day, now, zone).ok_or(TodoError::InvalidDate(day.clone()))?;
+            tasks.retain(|task| {
+                task.scheduled
+                    .is_some_and(|scheduled| zone.date_of(scheduled) == day)
+            });
+        }
+
+        if !self.include_waiting {
+            tasks.retain(|task| task.is_waiting(now) == self.waiting);
+        }
+
+        if let Some(filter) = &self.filter {
+            let filter = Filter::parse(filter, now, zone).map_err(TodoError::InvalidFilter)?;
+            tasks.retain(|task| filter.matches(task, zone));
+        }
+
+        self.sort
+            .clone()
+            .unwrap_or_default()
+            .sort(&mut tasks, |task| manager.urgency(task));
+        Ok(tasks)
+    }
+}
+
+/// A column of the task table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Status,
+    Priority,
+    Name,
+    Category,
+    Due,
+    Tags,
+    Subtasks,
+    BlockedBy,
+    Urgency,
+}
+
+const COLUMNS: &[(&str, Column)] = &[
+    ("id", Column::Id),
+    ("status", Column::Status),
+    ("priority", Column::Priority),
+    ("name", Column::Name),
+    ("category", Column::Category),
+    ("due", Column::Due),
+    ("tags", Column::Tags),
+    ("subtasks", Column::Subtasks),
+    ("blocked-by", Column::BlockedBy),
+    ("urgency", Column::Urgency),
+];
+
+/// Table columns in display order, written as comma-separated names, e.g.
+/// `id,name,due`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns(pub Vec<Column>);
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns(COLUMNS.iter().map(|(_, column)| *column).collect())
+    }
+}
+
+impl FromStr for Columns {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let columns = s
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                COLUMNS
+                    .iter()
+                    .find(|(column, _)| column.eq_ignore_ascii_case(name))
+                    .map(|(_, column)| *column)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+                        format!(
+                            "Unknown column '{}' (expected one of {})",
+                            name,
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if columns.is_empty() {
+            return Err("No columns given".to_string());
+        }
+        Ok(Columns(columns))
+    }
+}
+
+impl fmt::Display for Columns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self
+            .0
+            .iter()
+            .map(|column| {
+                COLUMNS
+                    .iter()
+                    .find(|(_, known)| known == column)
+                    .map_or("?", |(name, _)| *name)
+            })
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// Optional fields stored as the text typed on the command line, through
+/// their `FromStr` and `Display` implementations.
+mod text {
+    use serde::{Deserialize, Deserializer, Serializer, de};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| text.parse().map_err(de::Error::custom))
+            .transpose()
+    }
+}
@@ -8,11 +8,80 @@ pub enum TodoError {
     #[error("JSON serialization error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Task file is locked by another todo process: {}", .0.display())]
+    Locked(std::path::PathBuf),
+
+    #[error("Data file uses schema version {0}, which is newer than this version of todo supports")]
+    UnsupportedSchema(u32),
+
+    #[error("Invalid data file: {0}")]
+    InvalidData(String),
+
+    #[error("Only some tasks are loaded; changing them needs all of them")]
+    PartiallyLoaded,
+
     #[error("Task not found: {0}")]
     TaskNotFound(u64),
 
+    #[error("Task {0} has {1} unfinished subtask(s); complete them first or use --cascade")]
+    PendingSubtasks(u64, usize),
+
+    #[error("Task {0} is already completed")]
+    AlreadyCompleted(u64),
+
+    #[error("Task {0} can't depend on task {1}: that would create a dependency cycle")]
+    DependencyCycle(u64, u64),
+
+    #[error("Task {0} has no single annotation matching '{1}'")]
+    AnnotationNotFound(u64, String),
+
+    #[error("Task {0} has no annotations")]
+    NoAnnotations(u64),
+
+    #[error("A timer is already running on task {0}; stop it first")]
+    TimerRunning(u64),
+
+    #[error("No timer is running")]
+    NoTimerRunning,
+
+    #[error("Nothing to undo")]
+    NothingToUndo,
+
+    #[error("Nothing to redo")]
+    NothingToRedo,
+
+    #[error(
+        "Task {0} has changed since this was recorded; undoing or redoing it would overwrite that"
+    )]
+    JournalMismatch(u64),
+
     #[error("Invalid date format: {0}")]
     InvalidDate(String),
+
+    #[error("{0}")]
+    InvalidEstimate(String),
+
+    #[error("{0}")]
+    InvalidTimeZone(String),
+
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
+
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(crate::core::filter::FilterError),
+
+    #[error("Unknown report '{0}'; run `todo report` to list them")]
+    UnknownReport(String),
+
+    #[error("'{0}' is a report command and can't be used as a report name")]
+    ReservedReportName(String),
+
+    #[error("Invalid config file {path}: {message}")]
+    Config { path: String, message: String },
 }
 
 pub type Result<T> = std::result::Result<T, TodoError>;
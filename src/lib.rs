@@ -1,6 +1,5 @@
-pub mod error;
 pub mod core;
+pub mod error;
 
-pub mod ui;
 pub mod cli;
-
+pub mod ui;
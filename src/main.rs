@@ -1,22 +1,58 @@
 use anyhow::Result;
 use clap::Parser;
+use std::io::ErrorKind;
 use todolist::cli::args::Cli;
-use todolist::core::store::TodoManager;
 use todolist::ui::display::display_error;
+use todolist::ui::output::{Format, print_error};
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let mut manager = match TodoManager::new() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let output = Cli::requested_output();
+            // Help and version go to stdout as usual.
+            if output.is_table() || !e.use_stderr() {
+                e.exit();
+            }
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            print_error(output, message.trim_start_matches("error: "));
+            std::process::exit(e.exit_code());
+        }
+    };
+    let output = Format::from(cli.output);
+    let mut manager = match cli.open_manager() {
         Ok(mgr) => mgr,
         Err(e) => {
-            eprintln!("Failed to initialize todo manager: {}", e);
+            let message = format!("Failed to initialize todo manager: {}", e);
+            if output.is_table() {
+                eprintln!("{}", message);
+            } else {
+                print_error(output, &message);
+            }
             std::process::exit(1);
         }
     };
 
     if let Err(e) = cli.execute(&mut manager) {
-        display_error(&format!("Error: {}", e));
+        // The reader went away (e.g. `| head`); there is no one left to tell.
+        if is_broken_pipe(&e) {
+            std::process::exit(0);
+        }
+        if output.is_table() {
+            display_error(&format!("Error: {}", e));
+        } else {
+            print_error(output, &e.to_string());
+        }
         std::process::exit(1);
     }
     Ok(())
 }
+
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe)
+    })
+}
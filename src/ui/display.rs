@@ -1,18 +1,33 @@
+use crate::core::group::Group;
+use crate::core::report::{EstimateReport, EstimateRow, TimeReport};
+use crate::core::search::Search;
+use crate::core::store::TodoManager;
 use crate::core::task::{Priority, Status, Task};
-use chrono::Utc;
+use crate::core::time::Zone;
+use crate::core::view::{Column, Columns};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
 use colored::Colorize;
 use prettytable::{Cell, Row, Table};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use term::Terminal;
 
-pub struct PrettyTask<'a>(pub &'a Task);
+/// One-line summary of a task, with due dates described relative to the
+/// given "now" in the given zone.
+pub struct PrettyTask<'a>(pub &'a Task, pub DateTime<Utc>, pub &'a Zone);
 
 impl<'a> Display for PrettyTask<'a> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        let task = self.0;
+        let (task, now, zone) = (self.0, self.1, self.2);
 
         let status_icon = match task.status {
             Status::Pending => "◯".yellow().bold(),
+            Status::InProgress => "◐".cyan().bold(),
+            Status::Waiting => "◔".magenta(),
+            Status::Blocked => "⊘".red().bold(),
             Status::Completed => "✔".green().bold(),
+            Status::Cancelled => "✘".truecolor(128, 128, 128),
         };
 
         let priority_icon = match task.priority {
@@ -21,30 +36,26 @@ impl<'a> Display for PrettyTask<'a> {
             Priority::High => "⬆".yellow(),
             Priority::Critical => "⚠".red().bold(),
         };
-        let task_name = if task.status == Status::Completed {
+        let task_name = if task.status.is_closed() {
             task.name.strikethrough().truecolor(128, 128, 128)
-        } else if task.is_overdue() {
+        } else if task.is_overdue(now) {
             task.name.red().bold()
         } else {
             task.name.white()
         };
         let category = format!("[{}]", task.category).truecolor(180, 180, 180);
 
-        let due_info = if let Some(due) = task.due_date {
-            let now = Utc::now();
-            if task.is_overdue() {
+        let due_info = if let Some(days) = task.days_until_due(now, zone) {
+            if task.is_overdue(now) {
                 "OVERDUE".red().bold()
+            } else if days == 0 {
+                format!("Today{}", due_time(task, zone)).yellow().bold()
+            } else if days == 1 {
+                format!("Tomorrow{}", due_time(task, zone)).yellow()
+            } else if days < 0 {
+                format!("{} days ago", days.abs()).red()
             } else {
-                let days = (due - now).num_days();
-                if days == 0 {
-                    "Today".yellow().bold()
-                } else if days == 1 {
-                    "Tomorrow".yellow()
-                } else if days < 0 {
-                    format!("{} days ago", days.abs()).red()
-                } else {
-                    format!("in {} days", days).green()
-                }
+                format!("in {} days", days).green()
             }
         } else {
             "No due date".truecolor(128, 128, 128)
@@ -63,70 +74,508 @@ impl<'a> Display for PrettyTask<'a> {
     }
 }
 
-pub fn display_task_table(tasks: &[&Task]) {
+/// Optional extras for `display_task_table`.
+#[derive(Debug, Default, Clone)]
+pub struct TableOptions {
+    /// Print each task's annotations on rows below it.
+    pub annotations: bool,
+    /// Highlight what this search matched, including a snippet of
+    /// matching notes below the task.
+    pub highlight: Option<Search>,
+    /// Which columns to show, in order.
+    pub columns: Columns,
+}
+
+pub fn display_task_table(tasks: &[&Task], manager: &TodoManager, options: &TableOptions) {
     let mut table = Table::new();
 
+    let columns = &options.columns.0;
+    // Rows under a task span every column but the first.
+    let span = columns.len().saturating_sub(1).max(1);
+
     // table head
-    table.set_titles(Row::new(vec![
-        Cell::new("ID").style_spec("bFg"),
-        Cell::new("Status").style_spec("bFg"),
-        Cell::new("Priority").style_spec("bFg"),
-        Cell::new("Task").style_spec("bFg"),
-        Cell::new("Category").style_spec("bFg"),
-        Cell::new("Due Date").style_spec("bFg"),
-        Cell::new("Tags").style_spec("bFg"),
-    ]));
+    table.set_titles(Row::new(
+        columns
+            .iter()
+            .map(|column| {
+                let title = match column {
+                    Column::Id => "ID",
+                    Column::Status => "Status",
+                    Column::Priority => "Priority",
+                    Column::Name => "Task",
+                    Column::Category => "Category",
+                    Column::Due => "Due Date",
+                    Column::Tags => "Tags",
+                    Column::Subtasks => "Subtasks",
+                    Column::BlockedBy => "Blocked By",
+                    Column::Urgency => "Urgency",
+                };
+                Cell::new(title).style_spec("bFg")
+            })
+            .collect(),
+    ));
 
+    let search = options.highlight.as_ref();
     for task in tasks {
-        let status_cell = match task.status {
-            Status::Pending => Cell::new("PENDING").style_spec("Fy"),
-            Status::Completed => Cell::new("COMPLETED").style_spec("Fg"),
-        };
+        // Only work out the cells that are shown; some look at other tasks.
+        let cells = columns
+            .iter()
+            .map(|column| match column {
+                Column::Id => Cell::new(&task.id.to_string()),
+                Column::Status => match task.status {
+                    Status::Pending => Cell::new("PENDING").style_spec("Fy"),
+                    Status::InProgress => Cell::new("IN PROGRESS").style_spec("Fc"),
+                    Status::Waiting => Cell::new("WAITING").style_spec("Fm"),
+                    Status::Blocked => Cell::new("BLOCKED").style_spec("Fr"),
+                    Status::Completed => Cell::new("COMPLETED").style_spec("Fg"),
+                    Status::Cancelled => Cell::new("CANCELLED").style_spec("Fw"),
+                },
+                Column::Priority => match task.priority {
+                    Priority::Low => Cell::new("LOW").style_spec("Fb"),
+                    Priority::Medium => Cell::new("MEDIUM").style_spec("Fc"),
+                    Priority::High => Cell::new("HIGH").style_spec("Fy"),
+                    Priority::Critical => Cell::new("CRITICAL").style_spec("Fr"),
+                },
+                Column::Name => {
+                    let mut name = highlight(&task.name, search);
+                    if let Some(parent) = task.parent_id {
+                        name.push_str(&format!(" (↳ #{})", parent));
+                    }
+                    if let Some(recurrence) = &task.recurrence {
+                        name.push_str(&format!(" (↻ {})", recurrence));
+                    }
+                    Cell::new(&name)
+                }
+                Column::Category => Cell::new(&highlight(&task.category, search)),
+                Column::Due => match task.days_until_due(manager.now(), manager.zone()) {
+                    Some(_) if task.is_overdue(manager.now()) => {
+                        Cell::new("OVERDUE").style_spec("Fr")
+                    }
+                    Some(0) => Cell::new(&format!("TODAY{}", due_time(task, manager.zone())))
+                        .style_spec("Fy"),
+                    Some(days) if days > 0 => {
+                        Cell::new(&format!("in {} days", days)).style_spec("Fg")
+                    }
+                    Some(days) => Cell::new(&format!("{} days ago", days.abs())).style_spec("Fr"),
+                    None => Cell::new("-"),
+                },
+                Column::Tags if task.tags.is_empty() => Cell::new("-"),
+                Column::Tags => {
+                    let tags: Vec<String> =
+                        task.tags.iter().map(|tag| highlight(tag, search)).collect();
+                    Cell::new(&tags.join(", "))
+                }
+                Column::Subtasks => match manager.subtask_progress(task.id) {
+                    Some((done, total)) if done == total => {
+                        Cell::new(&format!("{}/{}", done, total)).style_spec("Fg")
+                    }
+                    Some((done, total)) => Cell::new(&format!("{}/{}", done, total)),
+                    None => Cell::new("-"),
+                },
+                Column::BlockedBy => {
+                    let blockers = manager.blockers(task.id);
+                    if blockers.is_empty() {
+                        Cell::new("-")
+                    } else {
+                        let ids: Vec<String> =
+                            blockers.iter().map(|t| format!("#{}", t.id)).collect();
+                        Cell::new(&ids.join(", ")).style_spec("Fr")
+                    }
+                }
+                Column::Urgency => {
+                    Cell::new(&format!("{:.1}", manager.urgency(task))).style_spec("r")
+                }
+            })
+            .collect();
+        table.add_row(Row::new(cells));
+
+        if options.annotations {
+            for annotation in &task.annotations {
+                table.add_row(Row::new(vec![
+                    Cell::new(""),
+                    Cell::new(&format!(
+                        "  {} {}",
+                        format_timestamp(annotation.at, manager.zone()),
+                        annotation.text
+                    ))
+                    .style_spec("Fw")
+                    .with_hspan(span),
+                ]));
+            }
+        }
+
+        if let Some(snippet) = search.and_then(|search| notes_snippet(&task.notes, search)) {
+            table.add_row(Row::new(vec![
+                Cell::new(""),
+                Cell::new(&format!("  ✎ {}", snippet)).with_hspan(span),
+            ]));
+        }
+    }
+    print_highlighted(&table);
+}
+
+// Search matches are wrapped in these zero-width characters while the table
+// is laid out, so column widths are measured on the plain text, and only
+// turned into colors once the table has been rendered.
+const HIGHLIGHT_START: char = '\u{2063}';
+const HIGHLIGHT_END: char = '\u{2064}';
+
+/// `text` with the parts `search` matched marked for highlighting.
+fn highlight(text: &str, search: Option<&Search>) -> String {
+    let Some(search) = search else {
+        return text.to_string();
+    };
+    let mut highlighted = String::new();
+    let mut last = 0;
+    for range in search.highlights(text) {
+        highlighted.push_str(&text[last..range.start]);
+        highlighted.push(HIGHLIGHT_START);
+        highlighted.push_str(&text[range.clone()]);
+        highlighted.push(HIGHLIGHT_END);
+        last = range.end;
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+/// Print `table` like `Table::printstd`, showing text marked by `highlight`
+/// black on yellow.
+fn print_highlighted(table: &Table) {
+    let terminal = io::stdout()
+        .is_terminal()
+        .then(|| term::TerminfoTerminal::new(Vec::new()))
+        .flatten();
+    let colorize = terminal.is_some() && colored::control::SHOULD_COLORIZE.should_colorize();
+    let rendered = match terminal {
+        Some(mut terminal) => table
+            .print_term(&mut terminal)
+            .map(|_| terminal.into_inner()),
+        None => {
+            let mut plain = Vec::new();
+            table.print(&mut plain).map(|_| plain)
+        }
+    };
+    let Ok(rendered) = rendered else {
+        return;
+    };
+    let (start, end) = if colorize {
+        ("\x1b[30;43m", "\x1b[0m")
+    } else {
+        ("", "")
+    };
+    let rendered = String::from_utf8_lossy(&rendered)
+        .replace(HIGHLIGHT_START, start)
+        .replace(HIGHLIGHT_END, end);
+    let _ = io::stdout().lock().write_all(rendered.as_bytes());
+}
+
+/// The line of `notes` with the first match, shortened to about 60
+/// characters around it.
+fn notes_snippet(notes: &str, search: &Search) -> Option<String> {
+    let line = notes
+        .lines()
+        .find(|line| !search.highlights(line).is_empty())?;
+    let first = search.highlights(line)[0].start;
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let at = chars.iter().position(|(i, _)| *i == first).unwrap_or(0);
+    let from = at.saturating_sub(20);
+    let to = (from + 60).min(chars.len());
+    let start = chars[from].0;
+    let end = chars.get(to).map_or(line.len(), |(i, _)| *i);
+    Some(format!(
+        "{}{}{}",
+        if from > 0 { "…" } else { "" },
+        highlight(&line[start..end], Some(search)),
+        if to < chars.len() { "…" } else { "" }
+    ))
+}
 
-        let priority_cell = match task.priority {
-            Priority::Low => Cell::new("LOW").style_spec("Fb"),
-            Priority::Medium => Cell::new("MEDIUM").style_spec("Fc"),
-            Priority::High => Cell::new("HIGH").style_spec("Fy"),
-            Priority::Critical => Cell::new("CRITICAL").style_spec("Fr"),
+/// Print `tasks` as an indented tree. Tasks whose parent is not among
+/// `tasks` are shown as roots.
+pub fn display_task_tree(tasks: &[&Task], now: DateTime<Utc>, zone: &Zone) {
+    fn print_children(
+        children: &HashMap<u64, Vec<&Task>>,
+        parent: u64,
+        prefix: &str,
+        now: DateTime<Utc>,
+        zone: &Zone,
+    ) {
+        let Some(tasks) = children.get(&parent) else {
+            return;
         };
+        for (i, task) in tasks.iter().enumerate() {
+            let last = i + 1 == tasks.len();
+            let branch = if last { "└── " } else { "├── " };
+            println!(
+                "{}{}#{} {}",
+                prefix,
+                branch.bright_black(),
+                task.id,
+                PrettyTask(task, now, zone)
+            );
+            let next_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_children(children, task.id, &next_prefix, now, zone);
+        }
+    }
+
+    let shown: HashSet<u64> = tasks.iter().map(|task| task.id).collect();
+    let mut children: HashMap<u64, Vec<&Task>> = HashMap::new();
+    for task in tasks {
+        if let Some(parent) = task.parent_id.filter(|parent| shown.contains(parent)) {
+            children.entry(parent).or_default().push(task);
+        }
+    }
+    for task in tasks {
+        let is_root = task.parent_id.is_none_or(|parent| !shown.contains(&parent));
+        if is_root {
+            println!("#{} {}", task.id, PrettyTask(task, now, zone));
+            print_children(&children, task.id, "", now, zone);
+        }
+    }
+}
+
+/// Task counts shown next to a category or group name.
+pub fn group_counts(group: &Group) -> String {
+    format!(
+        "{} tasks, {} completed",
+        group.tasks.len(),
+        group.completed()
+    )
+}
+
+/// Heading printed above each section of a grouped list.
+pub fn display_group_header(group: &Group) {
+    println!(
+        "\n{} {}",
+        format!("▸ {}", group.label).bright_cyan().bold(),
+        format!("({})", group_counts(group)).bright_black()
+    );
+}
+
+/// ` HH:MM` for tasks due at a particular time of day, empty for those due
+/// at the end of the day.
+fn due_time(task: &Task, zone: &Zone) -> String {
+    let Some(due) = task.due_date else {
+        return String::new();
+    };
+    let time = zone.localize(due).time();
+    if time == NaiveTime::from_hms_opt(23, 59, 59).unwrap() {
+        String::new()
+    } else {
+        time.format(" %H:%M").to_string()
+    }
+}
+
+/// `date` as wall-clock time in the configured zone.
+fn format_timestamp(date: DateTime<Utc>, zone: &Zone) -> String {
+    zone.localize(date).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Format a duration as hours and minutes, e.g. `2h 05m` or `45m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
 
-        let due_info = if let Some(due) = task.due_date {
-            let now = Utc::now();
-            if task.is_overdue() {
-                Cell::new("OVERDUE").style_spec("Fr")
+pub fn display_time_report(report: &TimeReport, zone: &Zone) {
+    let period = match report.since {
+        Some(since) => format!(
+            "{} to {}",
+            format_timestamp(since, zone),
+            format_timestamp(report.until, zone)
+        ),
+        None => format!("up to {}", format_timestamp(report.until, zone)),
+    };
+    println!(
+        "{} {}",
+        "Time report".bold(),
+        period.truecolor(128, 128, 128)
+    );
+
+    if report.by_task.is_empty() {
+        display_info("No time tracked in this period");
+        return;
+    }
+
+    fn section(title: &str, rows: Vec<(String, Duration)>) {
+        let mut table = Table::new();
+        table.set_titles(Row::new(vec![
+            Cell::new(title).style_spec("bFg"),
+            Cell::new("Time").style_spec("bFg"),
+        ]));
+        for (label, spent) in rows {
+            table.add_row(Row::new(vec![
+                Cell::new(&label),
+                Cell::new(&format_duration(spent)).style_spec("r"),
+            ]));
+        }
+        table.printstd();
+    }
+
+    section(
+        "Task",
+        report
+            .by_task
+            .iter()
+            .map(|(id, name, spent)| (format!("#{} {}", id, name), *spent))
+            .collect(),
+    );
+    section("Category", report.by_category.clone());
+    if !report.by_tag.is_empty() {
+        section("Tag", report.by_tag.clone());
+    }
+    println!("{} {}", "Total:".bold(), format_duration(report.total));
+}
+
+pub fn display_estimate_report(report: &EstimateReport) {
+    println!("{}", "Estimates vs. actual (completed tasks)".bold());
+    if report.rows.is_empty() {
+        display_info("No completed tasks with estimates");
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_titles(Row::new(vec![
+        Cell::new("Category").style_spec("bFg"),
+        Cell::new("Tasks").style_spec("bFg"),
+        Cell::new("Estimated").style_spec("bFg"),
+        Cell::new("Actual").style_spec("bFg"),
+        Cell::new("Actual/Est").style_spec("bFg"),
+        Cell::new("Points").style_spec("bFg"),
+        Cell::new("Time/Point").style_spec("bFg"),
+    ]));
+
+    let row = |row: &EstimateRow, style: &str| {
+        let ratio = match row.ratio() {
+            Some(ratio) if ratio > 1.2 => Cell::new(&format!("{:.2}", ratio)).style_spec("rFr"),
+            Some(ratio) if ratio < 0.8 => Cell::new(&format!("{:.2}", ratio)).style_spec("rFy"),
+            Some(ratio) => Cell::new(&format!("{:.2}", ratio)).style_spec("rFg"),
+            None => Cell::new("-").style_spec("r"),
+        };
+        let duration = |tasks: usize, duration: Duration| {
+            if tasks == 0 {
+                "-".to_string()
             } else {
-                let days = (due - now).num_days();
-                if days == 0 {
-                    Cell::new("TODAY").style_spec("Fy")
-                } else if days > 0 {
-                    Cell::new(&format!("in {} days", days)).style_spec("Fg")
-                } else {
-                    Cell::new(&format!("{} days ago", days.abs())).style_spec("Fr")
-                }
+                format_duration(duration)
             }
-        } else {
-            Cell::new("-")
-        };
-
-        let tags = if task.tags.is_empty() {
-            "-".to_string()
-        } else {
-            task.tags.join(", ")
         };
+        Row::new(vec![
+            Cell::new(&row.category).style_spec(style),
+            Cell::new(&(row.timed_tasks + row.pointed_tasks).to_string()).style_spec("r"),
+            Cell::new(&duration(row.timed_tasks, row.estimated)).style_spec("r"),
+            Cell::new(&duration(row.timed_tasks, row.actual)).style_spec("r"),
+            ratio,
+            Cell::new(&row.points.to_string()).style_spec("r"),
+            Cell::new(
+                &row.time_per_point()
+                    .map_or("-".to_string(), format_duration),
+            )
+            .style_spec("r"),
+        ])
+    };
 
-        table.add_row(Row::new(vec![
-            Cell::new(&task.id.to_string()),
-            status_cell,
-            priority_cell,
-            Cell::new(&task.name),
-            Cell::new(&task.category),
-            due_info,
-            Cell::new(&tags),
-        ]));
+    for category in &report.rows {
+        table.add_row(row(category, ""));
     }
+    table.add_row(row(&report.total, "b"));
     table.printstd();
 }
 
+/// Print every field of `task`, followed by its notes.
+pub fn display_task_detail(task: &Task, manager: &TodoManager) {
+    fn field(label: &str, value: impl Display) {
+        println!("  {:<12} {}", format!("{}:", label).bold(), value);
+    }
+
+    let zone = manager.zone();
+    println!(
+        "{} {}",
+        format!("#{}", task.id).bright_black(),
+        PrettyTask(task, manager.now(), zone)
+    );
+    println!();
+    field("Status", &task.status);
+    field("Priority", &task.priority);
+    field("Category", &task.category);
+    if !task.tags.is_empty() {
+        field("Tags", task.tags.join(", "));
+    }
+    if let Some(due) = task.due_date {
+        field("Due", format_timestamp(due, zone));
+    }
+    if let Some(scheduled) = task.scheduled {
+        field("Scheduled", format_timestamp(scheduled, zone));
+    }
+    if let Some(wait) = task.wait_until {
+        field("Wait until", format_timestamp(wait, zone));
+    }
+    if let Some(recurrence) = &task.recurrence {
+        field("Recurs", recurrence);
+    }
+    if let Some(estimate) = &task.estimate {
+        field("Estimate", estimate);
+    }
+    field("Created", format_timestamp(task.create_at, zone));
+    field("Updated", format_timestamp(task.update_at, zone));
+    if let Some(started) = task.started_at {
+        field("Started", format_timestamp(started, zone));
+    }
+    if let Some(completed) = task.completed_at {
+        field("Completed", format_timestamp(completed, zone));
+    }
+    if let Some(parent) = task.parent_id.and_then(|id| manager.get_task(id)) {
+        field("Parent", format!("#{} {}", parent.id, parent.name));
+    }
+    if !task.sessions.is_empty() {
+        let running = if task.is_tracking() { " (running)" } else { "" };
+        field(
+            "Tracked",
+            format!("{}{}", format_duration(task.tracked_time()), running),
+        );
+    }
+    if let Some((done, total)) = manager.subtask_progress(task.id) {
+        field("Subtasks", format!("{}/{} done", done, total));
+    }
+    if !task.depends_on.is_empty() {
+        let deps: Vec<String> = task
+            .depends_on
+            .iter()
+            .map(|id| match manager.get_task(*id) {
+                Some(dep) => format!("#{} ({})", id, dep.status),
+                None => format!("#{}", id),
+            })
+            .collect();
+        field("Depends on", deps.join(", "));
+    }
+    field("UUID", task.uuid);
+
+    if !task.annotations.is_empty() {
+        println!();
+        println!("{}", "Annotations:".bold());
+        for annotation in &task.annotations {
+            println!(
+                "  {} {}",
+                format_timestamp(annotation.at, zone).truecolor(128, 128, 128),
+                annotation.text
+            );
+        }
+    }
+
+    println!();
+    if task.notes.is_empty() {
+        println!("{}", "No notes".truecolor(128, 128, 128));
+    } else {
+        println!("{}", "Notes:".bold());
+        for line in task.notes.lines() {
+            println!("  {}", line);
+        }
+    }
+}
+
 pub fn display_welcome() {
     println!("{}", "✨ Todo List Manager".bright_cyan().bold());
     println!("{}", "=".repeat(40).bright_black());
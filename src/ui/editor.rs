@@ -0,0 +1,36 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::process::Command;
+use uuid::Uuid;
+
+/// Open `initial` in the user's editor (`$VISUAL`, then `$EDITOR`, then `vi`)
+/// and return the saved text with trailing whitespace removed.
+pub fn edit_text(name: &str, initial: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Editor command is empty"))?;
+
+    // A fresh, unguessable name that no one else can have created first.
+    let path = env::temp_dir().join(format!("todo-{}-{}.md", name, Uuid::new_v4()));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&path)?.write_all(initial.as_bytes())?;
+
+    let status = Command::new(program).args(parts).arg(&path).status();
+    let text = fs::read_to_string(&path);
+    fs::remove_file(&path)?;
+
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} exited with an error", editor)));
+    }
+    Ok(text?.trim_end().to_string())
+}
@@ -0,0 +1,467 @@
+//! Machine-readable output, selected with `--output`.
+//!
+//! Commands that show tasks (`list`, `next`, `search`, `report <name>`)
+//! print one record per task; `show` and the commands that change a task
+//! print the task as it is afterwards (`remove` prints it as it was).
+//! `complete` prints a list: the completed task, then the next occurrence
+//! if it recurs. `undo` and `redo` print the tasks they touched that still
+//! exist, `track` the timed task, and `category list` one record per
+//! category. `report` lists the reports and `report save` prints the one
+//! it saved, as `name`, `description` and `builtin`.
+//!
+//! `report time` prints one record per task, category and tag, then the
+//! total: `group` (`task`, `category`, `tag` or `total`), `id` (tasks
+//! only), `name` and `minutes`. `report estimates` prints one record per
+//! category, then the total: `category`, `tasks`, `estimated_minutes`,
+//! `actual_minutes`, `ratio` (actual over estimated), `points` and
+//! `minutes_per_point`.
+//!
+//! * `json`: a pretty-printed array of records, or a single object for
+//!   `show` and the commands that change one task
+//! * `jsonl`: one compact JSON record per line
+//! * `csv`: a header row, then one row per record (RFC 4180 quoting)
+//! * `tsv`: the same with tabs; tabs, newlines and backslashes in values
+//!   are written as `\t`, `\n` and `\\`
+//!
+//! Task records have the fields below. Dates are RFC 3339 in UTC and
+//! missing values are `null` in JSON and empty in CSV and TSV, where list
+//! fields are joined with commas and `annotations` is left out.
+//!
+//! | field                       | value                                                                    |
+//! |-----------------------------|--------------------------------------------------------------------------|
+//! | `id`, `uuid`                | the task's ID and stable UUID                                            |
+//! | `name`, `category`, `notes` | text                                                                     |
+//! | `status`                    | `pending`, `in-progress`, `waiting`, `blocked`, `completed`, `cancelled` |
+//! | `priority`                  | `low`, `medium`, `high` or `critical`                                    |
+//! | `tags`                      | list of text                                                             |
+//! | `due`, `scheduled`, `wait`  | date                                                                     |
+//! | `created`, `updated`        | date                                                                     |
+//! | `started`, `completed`      | date                                                                     |
+//! | `parent`                    | task ID                                                                  |
+//! | `depends_on`                | list of task IDs                                                         |
+//! | `blocked`                   | whether a dependency is unfinished                                       |
+//! | `urgency`                   | number, rounded to two decimals                                          |
+//! | `estimate`                  | as entered, e.g. `1h30m` or `3pts`                                       |
+//! | `tracked_minutes`           | total tracked time                                                       |
+//! | `recurrence`                | as entered, e.g. `weekly:mon,thu`                                        |
+//! | `annotations`               | list of `{"at": date, "text": text}` (JSON only)                         |
+//!
+//! With JSON output, errors are printed to stderr as `{"error": message}`.
+
+use crate::core::filter::StatusMatch;
+use crate::core::group::Group;
+use crate::core::report::{EstimateRow, TimeReport};
+use crate::core::store::TodoManager;
+use crate::core::task::Task;
+use crate::error::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+    Jsonl,
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    pub fn is_table(self) -> bool {
+        self == Format::Table
+    }
+}
+
+/// Something printed as a JSON object or a CSV/TSV row.
+pub trait Record: Serialize {
+    fn headers() -> &'static [&'static str];
+    fn row(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnotationRecord {
+    pub at: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskRecord {
+    pub id: u64,
+    pub uuid: String,
+    pub name: String,
+    pub status: String,
+    pub priority: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub due: Option<String>,
+    pub scheduled: Option<String>,
+    pub wait: Option<String>,
+    pub created: String,
+    pub updated: String,
+    pub started: Option<String>,
+    pub completed: Option<String>,
+    pub parent: Option<u64>,
+    pub depends_on: Vec<u64>,
+    pub blocked: bool,
+    pub urgency: f64,
+    pub estimate: Option<String>,
+    pub tracked_minutes: i64,
+    pub recurrence: Option<String>,
+    pub notes: String,
+    pub annotations: Vec<AnnotationRecord>,
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl TaskRecord {
+    pub fn new(task: &Task, manager: &TodoManager) -> Self {
+        Self {
+            id: task.id,
+            uuid: task.uuid.to_string(),
+            name: task.name.clone(),
+            status: StatusMatch::Is(task.status.clone()).to_string(),
+            priority: task.priority.to_string().to_lowercase(),
+            category: task.category.clone(),
+            tags: task.tags.clone(),
+            due: task.due_date.map(timestamp),
+            scheduled: task.scheduled.map(timestamp),
+            wait: task.wait_until.map(timestamp),
+            created: timestamp(task.create_at),
+            updated: timestamp(task.update_at),
+            started: task.started_at.map(timestamp),
+            completed: task.completed_at.map(timestamp),
+            parent: task.parent_id,
+            depends_on: task.depends_on.clone(),
+            blocked: manager.is_blocked(task.id),
+            urgency: (manager.urgency(task) * 100.0).round() / 100.0,
+            estimate: task.estimate.map(|estimate| estimate.to_string()),
+            tracked_minutes: task.tracked_time().num_minutes(),
+            recurrence: task.recurrence.as_ref().map(ToString::to_string),
+            notes: task.notes.clone(),
+            annotations: task
+                .annotations
+                .iter()
+                .map(|annotation| AnnotationRecord {
+                    at: timestamp(annotation.at),
+                    text: annotation.text.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Record for TaskRecord {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "id",
+            "uuid",
+            "name",
+            "status",
+            "priority",
+            "category",
+            "tags",
+            "due",
+            "scheduled",
+            "wait",
+            "created",
+            "updated",
+            "started",
+            "completed",
+            "parent",
+            "depends_on",
+            "blocked",
+            "urgency",
+            "estimate",
+            "tracked_minutes",
+            "recurrence",
+            "notes",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        fn text<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(ToString::to_string).unwrap_or_default()
+        }
+        let ids: Vec<String> = self.depends_on.iter().map(u64::to_string).collect();
+        vec![
+            self.id.to_string(),
+            self.uuid.clone(),
+            self.name.clone(),
+            self.status.clone(),
+            self.priority.clone(),
+            self.category.clone(),
+            self.tags.join(","),
+            text(&self.due),
+            text(&self.scheduled),
+            text(&self.wait),
+            self.created.clone(),
+            self.updated.clone(),
+            text(&self.started),
+            text(&self.completed),
+            text(&self.parent),
+            ids.join(","),
+            self.blocked.to_string(),
+            self.urgency.to_string(),
+            text(&self.estimate),
+            self.tracked_minutes.to_string(),
+            text(&self.recurrence),
+            self.notes.clone(),
+        ]
+    }
+}
+
+/// A category with its task counts, as shown by `category list`.
+#[derive(Debug, Serialize)]
+pub struct CategoryRecord {
+    pub category: String,
+    pub tasks: usize,
+    pub completed: usize,
+}
+
+impl CategoryRecord {
+    pub fn new(group: &Group) -> Self {
+        Self {
+            category: group.label.clone(),
+            tasks: group.tasks.len(),
+            completed: group.completed(),
+        }
+    }
+}
+
+impl Record for CategoryRecord {
+    fn headers() -> &'static [&'static str] {
+        &["category", "tasks", "completed"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.category.clone(),
+            self.tasks.to_string(),
+            self.completed.to_string(),
+        ]
+    }
+}
+
+/// One line of `report time`.
+#[derive(Debug, Serialize)]
+pub struct TimeRecord {
+    pub group: &'static str,
+    pub id: Option<u64>,
+    pub name: String,
+    pub minutes: i64,
+}
+
+impl TimeRecord {
+    /// Every line of `report`: tasks, categories, tags, then the total.
+    pub fn all(report: &TimeReport) -> Vec<Self> {
+        let record = |group, id, name: &str, spent: &Duration| TimeRecord {
+            group,
+            id,
+            name: name.to_string(),
+            minutes: spent.num_minutes(),
+        };
+        let mut records: Vec<TimeRecord> = report
+            .by_task
+            .iter()
+            .map(|(id, name, spent)| record("task", Some(*id), name, spent))
+            .collect();
+        records.extend(
+            report
+                .by_category
+                .iter()
+                .map(|(name, spent)| record("category", None, name, spent)),
+        );
+        records.extend(
+            report
+                .by_tag
+                .iter()
+                .map(|(name, spent)| record("tag", None, name, spent)),
+        );
+        records.push(record("total", None, "Total", &report.total));
+        records
+    }
+}
+
+impl Record for TimeRecord {
+    fn headers() -> &'static [&'static str] {
+        &["group", "id", "name", "minutes"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.group.to_string(),
+            self.id.map(|id| id.to_string()).unwrap_or_default(),
+            self.name.clone(),
+            self.minutes.to_string(),
+        ]
+    }
+}
+
+/// One category, or the total, of `report estimates`.
+#[derive(Debug, Serialize)]
+pub struct EstimateRecord {
+    pub category: String,
+    pub tasks: usize,
+    pub estimated_minutes: i64,
+    pub actual_minutes: i64,
+    pub ratio: Option<f64>,
+    pub points: u64,
+    pub minutes_per_point: Option<i64>,
+}
+
+impl EstimateRecord {
+    pub fn new(row: &EstimateRow) -> Self {
+        Self {
+            category: row.category.clone(),
+            tasks: row.timed_tasks + row.pointed_tasks,
+            estimated_minutes: row.estimated.num_minutes(),
+            actual_minutes: row.actual.num_minutes(),
+            ratio: row.ratio().map(|ratio| (ratio * 100.0).round() / 100.0),
+            points: row.points,
+            minutes_per_point: row.time_per_point().map(|time| time.num_minutes()),
+        }
+    }
+}
+
+impl Record for EstimateRecord {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "category",
+            "tasks",
+            "estimated_minutes",
+            "actual_minutes",
+            "ratio",
+            "points",
+            "minutes_per_point",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        fn text<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(ToString::to_string).unwrap_or_default()
+        }
+        vec![
+            self.category.clone(),
+            self.tasks.to_string(),
+            self.estimated_minutes.to_string(),
+            self.actual_minutes.to_string(),
+            text(&self.ratio),
+            self.points.to_string(),
+            text(&self.minutes_per_point),
+        ]
+    }
+}
+
+/// A saved or built-in report, as listed by `report`.
+#[derive(Debug, Serialize)]
+pub struct ReportRecord {
+    pub name: String,
+    pub description: Option<String>,
+    pub builtin: bool,
+}
+
+impl Record for ReportRecord {
+    fn headers() -> &'static [&'static str] {
+        &["name", "description", "builtin"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.description.clone().unwrap_or_default(),
+            self.builtin.to_string(),
+        ]
+    }
+}
+
+/// Print `records` as a list. Write errors, such as a closed pipe, are
+/// returned rather than panicking.
+pub fn print_records<R: Record>(format: Format, records: &[R]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        Format::Table => {}
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(records)?)?,
+        Format::Jsonl => {
+            for record in records {
+                writeln!(out, "{}", serde_json::to_string(record)?)?;
+            }
+        }
+        Format::Csv | Format::Tsv => {
+            let line = |fields: Vec<String>| -> String {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| match format {
+                        Format::Csv => csv_field(field),
+                        _ => tsv_field(field),
+                    })
+                    .collect();
+                fields.join(if format == Format::Csv { "," } else { "\t" })
+            };
+            writeln!(
+                out,
+                "{}",
+                line(R::headers().iter().map(|h| h.to_string()).collect())
+            )?;
+            for record in records {
+                writeln!(out, "{}", line(record.row()))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Print a single record: an object rather than an array in JSON.
+pub fn print_record<R: Record>(format: Format, record: &R) -> Result<()> {
+    match format {
+        Format::Json => {
+            let mut out = io::stdout().lock();
+            writeln!(out, "{}", serde_json::to_string_pretty(record)?)?;
+            out.flush()?;
+        }
+        _ => print_records(format, std::slice::from_ref(record))?,
+    }
+    Ok(())
+}
+
+pub fn print_tasks(format: Format, tasks: &[&Task], manager: &TodoManager) -> Result<()> {
+    let records: Vec<TaskRecord> = tasks
+        .iter()
+        .map(|task| TaskRecord::new(task, manager))
+        .collect();
+    print_records(format, &records)
+}
+
+pub fn print_task(format: Format, task: &Task, manager: &TodoManager) -> Result<()> {
+    print_record(format, &TaskRecord::new(task, manager))
+}
+
+/// Report an error on stderr, keeping stdout to records; as a JSON object
+/// when JSON output is selected.
+pub fn print_error(format: Format, message: &str) {
+    match format {
+        Format::Json | Format::Jsonl => {
+            eprintln!("{}", serde_json::json!({ "error": message }))
+        }
+        _ => eprintln!("Error: {}", message),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn tsv_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}